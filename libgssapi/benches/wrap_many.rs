@@ -0,0 +1,74 @@
+//! Compare wrapping 10k 64 byte messages one at a time with `wrap`
+//! against wrapping them in one `wrap_many` call, over a krb5 context
+//! established in process. Like the loopback test this needs a
+//! working kerberos environment, and only runs when
+//! `GSS_TEST_PRINCIPAL` is set to the host based service name to use,
+//! e.g.
+//!
+//! `GSS_TEST_PRINCIPAL=nfs@server.example.com cargo bench --bench wrap_many`
+use libgssapi::{
+    context::{ClientCtx, CtxFlags, SecurityContext, ServerCtx},
+    credential::{Cred, CredUsage},
+    error::Error,
+    name::Name,
+    oid::{OidSet, GSS_MECH_KRB5, GSS_NT_HOSTBASED_SERVICE},
+    util::Buf,
+};
+use std::{env, time::Instant};
+
+const MESSAGES: usize = 10_000;
+const MESSAGE_SIZE: usize = 64;
+const ROUNDS: usize = 5;
+
+fn establish(service: &str) -> Result<ClientCtx, Error> {
+    let mut mechs = OidSet::new()?;
+    mechs.add(&GSS_MECH_KRB5)?;
+    let name = Name::new(service.as_bytes(), Some(&GSS_NT_HOSTBASED_SERVICE))?;
+    let cname = name.canonicalize(Some(&GSS_MECH_KRB5))?;
+    let server_cred = Cred::acquire(Some(&cname), None, CredUsage::Accept, Some(&mechs))?;
+    let client_cred = Cred::acquire(None, None, CredUsage::Initiate, Some(&mechs))?;
+    let mut server_ctx = ServerCtx::new(Some(server_cred), None);
+    let mut client_ctx = ClientCtx::new(
+        client_cred,
+        cname,
+        CtxFlags::GSS_C_MUTUAL_FLAG,
+        Some(&GSS_MECH_KRB5),
+        None,
+    );
+    let mut server_tok: Option<Buf> = None;
+    loop {
+        match client_ctx.step(server_tok.as_deref())? {
+            None => break,
+            Some(client_tok) => match server_ctx.step(&client_tok)? {
+                None => break,
+                Some(tok) => server_tok = Some(tok),
+            },
+        }
+    }
+    Ok(client_ctx)
+}
+
+fn main() -> Result<(), Error> {
+    let service = match env::var("GSS_TEST_PRINCIPAL") {
+        Ok(service) => service,
+        Err(_) => return Ok(()),
+    };
+    let mut ctx = establish(&service)?;
+    let data = vec![0x5a; MESSAGES * MESSAGE_SIZE];
+    let msgs = data.chunks(MESSAGE_SIZE).collect::<Vec<_>>();
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        let mut wrapped = Vec::with_capacity(MESSAGES);
+        for msg in &msgs {
+            wrapped.push(ctx.wrap(true, msg)?);
+        }
+        let single = start.elapsed();
+        drop(wrapped);
+        let start = Instant::now();
+        let wrapped = ctx.wrap_many(true, &msgs)?;
+        let many = start.elapsed();
+        drop(wrapped);
+        println!("wrap: {:?}, wrap_many: {:?}", single, many);
+    }
+    Ok(())
+}
@@ -26,7 +26,7 @@ fn setup_server_ctx(
     let server_cred =
         Cred::acquire(Some(&cname), None, CredUsage::Accept, Some(desired_mechs))?;
     println!("acquired server credentials: {:#?}", server_cred.info()?);
-    Ok((ServerCtx::new(server_cred), cname))
+    Ok((ServerCtx::new(Some(server_cred), None), cname))
 }
 
 fn setup_client_ctx(
@@ -44,6 +44,7 @@ fn setup_client_ctx(
         service_name,
         CtxFlags::GSS_C_MUTUAL_FLAG,
         Some(&GSS_MECH_KRB5),
+        None,
     ))
 }
 
@@ -183,7 +184,7 @@ fn run(service_name: &[u8]) -> Result<(), Error> {
     let mut client_ctx = setup_client_ctx(cname, &desired_mechs)?;
     let mut server_tok: Option<Buf> = None;
     loop {
-        match client_ctx.step(server_tok.as_ref().map(|b| &**b), None)? {
+        match client_ctx.step(server_tok.as_ref().map(|b| &**b))? {
             None => break,
             Some(client_tok) => match server_ctx.step(&*client_tok)? {
                 None => break,
@@ -1,30 +1,47 @@
 #[cfg(feature = "iov")]
 use crate::util::{GssIov, GssIovFake};
 use crate::{
-    credential::Cred,
-    error::{gss_error, Error, MajorFlags},
+    credential::{Cred, CredUsage},
+    error::{gss_error, Error, ErrorDetail, MajorFlags, StepError, SupplementaryFlags},
     name::Name,
     oid::{Oid, NO_OID},
-    util::{Buf, BufRef},
+    util::{time_from_c, time_to_c, Buf, BufRef, BufferSet},
 };
+#[cfg(any(feature = "mit", feature = "heimdal"))]
+use crate::{oid::GSS_INQ_SSPI_SESSION_KEY, util::zero};
+use bytes::Bytes;
+#[cfg(feature = "iov")]
+use bytes::BytesMut;
 use libgssapi_sys::{
-    size_t,
-    gss_OID, gss_accept_sec_context, gss_buffer_desc, gss_channel_bindings_struct,
-    gss_channel_bindings_t, gss_cred_id_struct, gss_cred_id_t, gss_ctx_id_t,
-    gss_delete_sec_context, gss_init_sec_context, gss_inquire_context, gss_name_t,
-    gss_unwrap, gss_wrap, OM_uint32, GSS_C_ANON_FLAG, GSS_C_CONF_FLAG, GSS_C_DELEG_FLAG,
-    GSS_C_DELEG_POLICY_FLAG, GSS_C_INTEG_FLAG, GSS_C_MUTUAL_FLAG, GSS_C_PROT_READY_FLAG,
+    gss_OID, gss_OID_desc, gss_accept_sec_context, gss_buffer_desc, gss_buffer_set_desc,
+    gss_buffer_set_t, gss_channel_bindings_struct, gss_channel_bindings_t,
+    gss_context_time, gss_cred_id_struct, gss_cred_id_t, gss_ctx_id_struct, gss_ctx_id_t,
+    gss_delete_sec_context, gss_export_sec_context, gss_get_mic, gss_import_sec_context,
+    gss_init_sec_context, gss_inquire_context, gss_inquire_sec_context_by_oid,
+    gss_name_t, gss_process_context_token, gss_pseudo_random, gss_set_sec_context_option,
+    gss_unwrap, gss_verify_mic, gss_wrap, gss_wrap_size_limit, size_t, ssize_t,
+    OM_uint32, _GSS_S_CONTINUE_NEEDED, _GSS_S_UNAVAILABLE, GSS_C_ANON_FLAG,
+    GSS_C_CONF_FLAG, GSS_C_DELEG_FLAG, GSS_C_DELEG_POLICY_FLAG, GSS_C_INTEG_FLAG,
+    GSS_C_MUTUAL_FLAG, GSS_C_PRF_KEY_FULL, GSS_C_PRF_KEY_PARTIAL, GSS_C_PROT_READY_FLAG,
     GSS_C_QOP_DEFAULT, GSS_C_REPLAY_FLAG, GSS_C_SEQUENCE_FLAG, GSS_C_TRANS_FLAG,
-    GSS_S_COMPLETE, _GSS_C_INDEFINITE, _GSS_S_CONTINUE_NEEDED,
+    GSS_S_COMPLETE,
 };
 #[cfg(feature = "iov")]
 use libgssapi_sys::{
     gss_iov_buffer_desc, gss_unwrap_iov, gss_wrap_iov, gss_wrap_iov_length,
+    GSS_IOV_BUFFER_TYPE_DATA, GSS_IOV_BUFFER_TYPE_HEADER, GSS_IOV_BUFFER_TYPE_PADDING,
+    GSS_IOV_BUFFER_TYPE_TRAILER,
 };
-use std::{ffi, ptr, time::Duration, os::raw::c_int};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(any(feature = "mit", feature = "heimdal"))]
+use std::fmt;
+use std::{ffi, os::raw::c_int, ptr, thread, time::Duration};
 
 bitflags! {
     pub struct CtxFlags: u32 {
+        /// Unconditionally delegate the initiator's credentials to the
+        /// acceptor.
         const GSS_C_DELEG_FLAG = GSS_C_DELEG_FLAG;
         const GSS_C_MUTUAL_FLAG = GSS_C_MUTUAL_FLAG;
         const GSS_C_REPLAY_FLAG = GSS_C_REPLAY_FLAG;
@@ -34,6 +51,11 @@ bitflags! {
         const GSS_C_ANON_FLAG = GSS_C_ANON_FLAG;
         const GSS_C_PROT_READY_FLAG = GSS_C_PROT_READY_FLAG;
         const GSS_C_TRANS_FLAG = GSS_C_TRANS_FLAG;
+        /// Delegate only if policy allows it, for krb5 that is when the
+        /// KDC marked the target service OK-AS-DELEGATE. This is the
+        /// safer choice, request it instead of `GSS_C_DELEG_FLAG` and
+        /// check whether `GSS_C_DELEG_FLAG` is set in the established
+        /// context's `flags` to learn if delegation actually happened.
         const GSS_C_DELEG_POLICY_FLAG = GSS_C_DELEG_POLICY_FLAG;
     }
 }
@@ -51,7 +73,56 @@ fn delete_ctx(mut ctx: gss_ctx_id_t) {
     }
 }
 
-unsafe fn wrap(ctx: gss_ctx_id_t, encrypt: bool, msg: &[u8]) -> Result<Buf, Error> {
+unsafe fn delete(ctx: &mut gss_ctx_id_t) -> Result<Option<Buf>, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut token = Buf::empty();
+    let major = gss_delete_sec_context(
+        &mut minor as *mut OM_uint32,
+        ctx as *mut gss_ctx_id_t,
+        token.to_c(),
+    );
+    if major == GSS_S_COMPLETE {
+        Ok(if token.is_empty() { None } else { Some(token) })
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_unchecked(major),
+            minor,
+            detail: None,
+            mech: ctx_mech(*ctx),
+        })
+    }
+}
+
+/// A mechanism specific quality of protection for per message
+/// operations. Mechanisms define what values they accept, the krb5
+/// mechanism only accepts `Qop::DEFAULT`, anything else fails with
+/// `GSS_S_BAD_QOP`.
+///
+/// There is no way to ask a mechanism which values it supports,
+/// gssapi has no such call (`gss_inquire_attrs_for_mech` reports
+/// mechanism attributes, not QOPs), so the values to use must come
+/// from the mechanism's documentation. Use `Qop::DEFAULT` unless you
+/// know otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Qop(pub u32);
+
+impl Qop {
+    /// The mechanism's default quality of protection
+    pub const DEFAULT: Qop = Qop(GSS_C_QOP_DEFAULT);
+}
+
+impl Default for Qop {
+    fn default() -> Self {
+        Qop::DEFAULT
+    }
+}
+
+unsafe fn wrap(
+    ctx: gss_ctx_id_t,
+    encrypt: bool,
+    qop: Qop,
+    msg: &[u8],
+) -> Result<Buf, Error> {
     let mut minor = GSS_S_COMPLETE;
     let mut msg = BufRef::from(msg);
     let mut enc_msg = Buf::empty();
@@ -59,7 +130,7 @@ unsafe fn wrap(ctx: gss_ctx_id_t, encrypt: bool, msg: &[u8]) -> Result<Buf, Erro
         &mut minor as *mut OM_uint32,
         ctx,
         if encrypt { 1 } else { 0 },
-        GSS_C_QOP_DEFAULT,
+        qop.0,
         msg.to_c(),
         ptr::null_mut(),
         enc_msg.to_c(),
@@ -70,10 +141,122 @@ unsafe fn wrap(ctx: gss_ctx_id_t, encrypt: bool, msg: &[u8]) -> Result<Buf, Erro
         Err(Error {
             major: MajorFlags::from_bits_unchecked(major),
             minor,
+            detail: None,
+            mech: ctx_mech(ctx),
         })
     }
 }
 
+#[cfg(feature = "iov")]
+unsafe fn wrap_many(
+    ctx: gss_ctx_id_t,
+    encrypt: bool,
+    msgs: &[&[u8]],
+) -> Result<Vec<Bytes>, Error> {
+    let qop = Qop::DEFAULT;
+    let mut minor = GSS_S_COMPLETE;
+    let conf_req = if encrypt { 1 } else { 0 };
+    let iov_buf = |type_| gss_iov_buffer_desc {
+        type_,
+        buffer: gss_buffer_desc {
+            length: 0,
+            value: ptr::null_mut(),
+        },
+    };
+    let mut iov = [
+        iov_buf(GSS_IOV_BUFFER_TYPE_HEADER),
+        iov_buf(GSS_IOV_BUFFER_TYPE_DATA),
+        iov_buf(GSS_IOV_BUFFER_TYPE_PADDING),
+        iov_buf(GSS_IOV_BUFFER_TYPE_TRAILER),
+    ];
+    let fail = |major, minor| Error {
+        major: MajorFlags::from_bits_unchecked(major),
+        minor,
+        detail: None,
+        mech: ctx_mech(ctx),
+    };
+    // The header, padding and trailer sizes only depend on the message
+    // length, so only ask when it changes, which for a batch of same
+    // sized messages is once.
+    let mut sizes = Vec::with_capacity(msgs.len());
+    let mut last: Option<(usize, [usize; 3])> = None;
+    let mut total = 0;
+    for msg in msgs {
+        let s = match last {
+            Some((len, s)) if len == msg.len() => s,
+            _ => {
+                for b in iov.iter_mut() {
+                    b.buffer.length = 0;
+                }
+                iov[1].buffer.length = msg.len() as size_t;
+                let major = gss_wrap_iov_length(
+                    &mut minor as *mut OM_uint32,
+                    ctx,
+                    conf_req,
+                    qop.0,
+                    ptr::null_mut(),
+                    iov.as_mut_ptr(),
+                    iov.len() as c_int,
+                );
+                if major != GSS_S_COMPLETE {
+                    return Err(fail(major, minor));
+                }
+                let s = [
+                    iov[0].buffer.length as usize,
+                    iov[2].buffer.length as usize,
+                    iov[3].buffer.length as usize,
+                ];
+                last = Some((msg.len(), s));
+                s
+            }
+        };
+        total += s[0] + msg.len() + s[1] + s[2];
+        sizes.push(s);
+    }
+    // wrap every message in place into one shared buffer, laid out
+    // HEADER | DATA | PADDING | TRAILER, which is the same as the
+    // token `gss_wrap` would produce
+    let mut buf = BytesMut::with_capacity(total);
+    buf.resize(total, 0);
+    let mut out = Vec::with_capacity(msgs.len());
+    for (msg, [header, padding, trailer]) in msgs.iter().zip(sizes) {
+        let mut tok = buf.split_to(header + msg.len() + padding + trailer);
+        tok[header..header + msg.len()].copy_from_slice(msg);
+        let base = tok.as_mut_ptr();
+        let mut offset = 0;
+        for (b, len) in iov.iter_mut().zip([header, msg.len(), padding, trailer]) {
+            b.buffer.length = len as size_t;
+            b.buffer.value = base.add(offset).cast();
+            offset += len;
+        }
+        let major = gss_wrap_iov(
+            &mut minor as *mut OM_uint32,
+            ctx,
+            conf_req,
+            qop.0,
+            ptr::null_mut(),
+            iov.as_mut_ptr(),
+            iov.len() as c_int,
+        );
+        if major != GSS_S_COMPLETE {
+            return Err(fail(major, minor));
+        }
+        // the mechanism may use less padding than it asked for, close
+        // the gap so the token stays contiguous
+        let used = iov[2].buffer.length as usize;
+        if used < padding {
+            let trailer_at = header + msg.len() + padding;
+            tok.copy_within(
+                trailer_at..trailer_at + trailer,
+                trailer_at - (padding - used),
+            );
+            tok.truncate(tok.len() - (padding - used));
+        }
+        out.push(tok.freeze());
+    }
+    Ok(out)
+}
+
 #[cfg(feature = "iov")]
 unsafe fn wrap_iov(
     ctx: gss_ctx_id_t,
@@ -96,6 +279,8 @@ unsafe fn wrap_iov(
         Err(Error {
             major: MajorFlags::from_bits_unchecked(major),
             minor,
+            detail: None,
+            mech: ctx_mech(ctx),
         })
     }
 }
@@ -122,28 +307,362 @@ unsafe fn wrap_iov_length(
         Err(Error {
             major: MajorFlags::from_bits_unchecked(major),
             minor,
+            detail: None,
+            mech: ctx_mech(ctx),
         })
     }
 }
 
-unsafe fn unwrap(ctx: gss_ctx_id_t, msg: &[u8]) -> Result<Buf, Error> {
+/// How an unwrapped message was protected by the sender
+#[derive(Debug, Clone, Copy)]
+pub struct UnwrapInfo {
+    /// true if the message was encrypted, false if it was only
+    /// integrity protected
+    pub encrypted: bool,
+    /// the quality of protection the sender used
+    pub qop: Qop,
+    /// whether the message arrived out of order, duplicated, etc
+    pub supplementary: SupplementaryFlags,
+}
+
+/// How a verified message was protected by the sender, see
+/// `SecurityContext::verify_mic_info`
+#[derive(Debug, Clone, Copy)]
+pub struct MicInfo {
+    /// the quality of protection the sender used
+    pub qop: Qop,
+    /// whether the message arrived out of order, duplicated, etc
+    pub supplementary: SupplementaryFlags,
+}
+
+// Split the supplementary bits out of a major status that has no
+// errors. If `strict` then any supplementary bits are reported as an
+// error instead.
+fn supplementary(major: OM_uint32, strict: bool) -> Option<SupplementaryFlags> {
+    let sup = SupplementaryFlags::from_bits_truncate(major);
+    if gss_error(major) > 0 || (strict && !sup.is_empty()) {
+        None
+    } else {
+        Some(sup)
+    }
+}
+
+unsafe fn unwrap(
+    ctx: gss_ctx_id_t,
+    msg: &[u8],
+    strict: bool,
+) -> Result<(Buf, UnwrapInfo), Error> {
     let mut minor = GSS_S_COMPLETE;
     let mut msg = BufRef::from(msg);
     let mut out = Buf::empty();
+    let mut conf_state: c_int = 0;
+    let mut qop: OM_uint32 = 0;
     let major = gss_unwrap(
         &mut minor as *mut OM_uint32,
         ctx,
         msg.to_c(),
         out.to_c(),
-        ptr::null_mut::<i32>(),
-        ptr::null_mut::<OM_uint32>(),
+        &mut conf_state as *mut c_int,
+        &mut qop as *mut OM_uint32,
     );
-    if major == GSS_S_COMPLETE {
+    if let Some(supplementary) = supplementary(major, strict) {
+        let encrypted = conf_state != 0;
+        Ok((
+            out,
+            UnwrapInfo {
+                encrypted,
+                qop: Qop(qop),
+                supplementary,
+            },
+        ))
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_unchecked(major),
+            minor,
+            detail: None,
+            mech: ctx_mech(ctx),
+        })
+    }
+}
+
+/// Which key the pseudo random function should be keyed with, see
+/// RFC 4401.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrfKey {
+    /// Use the key with the strongest protection the mechanism
+    /// supports (e.g. the acceptor subkey in krb5 if there is one)
+    Full,
+    /// Use a key that is always available to both sides
+    Partial,
+}
+
+impl PrfKey {
+    fn to_c(self) -> c_int {
+        match self {
+            PrfKey::Full => GSS_C_PRF_KEY_FULL as c_int,
+            PrfKey::Partial => GSS_C_PRF_KEY_PARTIAL as c_int,
+        }
+    }
+}
+
+unsafe fn pseudo_random(
+    ctx: gss_ctx_id_t,
+    prf_key: PrfKey,
+    input: &[u8],
+    desired_output_len: usize,
+) -> Result<Buf, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut input = BufRef::from(input);
+    let mut out = Buf::empty();
+    let major = gss_pseudo_random(
+        &mut minor as *mut OM_uint32,
+        ctx,
+        prf_key.to_c(),
+        input.to_c(),
+        desired_output_len as ssize_t,
+        out.to_c(),
+    );
+    if major != GSS_S_COMPLETE {
+        Err(Error {
+            major: MajorFlags::from_bits_unchecked(major),
+            minor,
+            detail: None,
+            mech: ctx_mech(ctx),
+        })
+    } else if out.len() != desired_output_len {
+        Err(Error {
+            major: MajorFlags::GSS_S_FAILURE,
+            minor: 0,
+            detail: None,
+            mech: ctx_mech(ctx),
+        })
+    } else {
         Ok(out)
+    }
+}
+
+unsafe fn export(ctx: &mut gss_ctx_id_t) -> Result<Buf, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut token = Buf::empty();
+    let major = gss_export_sec_context(
+        &mut minor as *mut OM_uint32,
+        ctx as *mut gss_ctx_id_t,
+        token.to_c(),
+    );
+    if major == GSS_S_COMPLETE {
+        Ok(token)
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_unchecked(major),
+            minor,
+            detail: None,
+            mech: ctx_mech(*ctx),
+        })
+    }
+}
+
+unsafe fn process_token(ctx: gss_ctx_id_t, token: &[u8]) -> Result<(), Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut token = BufRef::from(token);
+    let major =
+        gss_process_context_token(&mut minor as *mut OM_uint32, ctx, token.to_c());
+    if major == GSS_S_COMPLETE {
+        Ok(())
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_unchecked(major),
+            minor,
+            detail: None,
+            mech: ctx_mech(ctx),
+        })
+    }
+}
+
+unsafe fn set_option(
+    ctx: &mut gss_ctx_id_t,
+    desired_object: &Oid,
+    value: &[u8],
+) -> Result<bool, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut value = BufRef::from(value);
+    let major = gss_set_sec_context_option(
+        &mut minor as *mut OM_uint32,
+        ctx as *mut gss_ctx_id_t,
+        desired_object.to_c(),
+        value.to_c(),
+    );
+    if major == GSS_S_COMPLETE {
+        Ok(true)
+    } else if major == _GSS_S_UNAVAILABLE {
+        Ok(false)
     } else {
         Err(Error {
             major: MajorFlags::from_bits_unchecked(major),
             minor,
+            detail: None,
+            mech: ctx_mech(*ctx),
+        })
+    }
+}
+
+unsafe fn inquire_by_oid(ctx: gss_ctx_id_t, oid: &Oid) -> Result<BufferSet, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut out = ptr::null_mut::<gss_buffer_set_desc>();
+    let major = gss_inquire_sec_context_by_oid(
+        &mut minor as *mut OM_uint32,
+        ctx,
+        oid.to_c(),
+        &mut out as *mut gss_buffer_set_t,
+    );
+    if major == GSS_S_COMPLETE {
+        Ok(BufferSet::from_c(out))
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_unchecked(major),
+            minor,
+            detail: None,
+            mech: ctx_mech(ctx),
+        })
+    }
+}
+
+/// The session key of a security context, see
+/// `SecurityContext::session_key`. The buffer gssapi returned the key
+/// in is zeroed as soon as the key has been copied out of it, and the
+/// copy held here is zeroed when this is dropped. The key is only
+/// lent out by `key`, and this isn't `Clone` and its `Debug` output
+/// doesn't include the key, but any copy the caller makes of it is
+/// theirs to zero.
+#[cfg(any(feature = "mit", feature = "heimdal"))]
+pub struct SessionKey {
+    key: Vec<u8>,
+    /// the encoded oid identifying the encryption type of the key,
+    /// for krb5 the last component is the enctype number
+    pub enctype_oid: Vec<u8>,
+}
+
+#[cfg(any(feature = "mit", feature = "heimdal"))]
+impl fmt::Debug for SessionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("SessionKey")
+            .field("key", &"<redacted>")
+            .field("enctype_oid", &self.enctype_oid)
+            .finish()
+    }
+}
+
+#[cfg(any(feature = "mit", feature = "heimdal"))]
+impl Drop for SessionKey {
+    fn drop(&mut self) {
+        zero(&mut self.key);
+    }
+}
+
+#[cfg(any(feature = "mit", feature = "heimdal"))]
+impl SessionKey {
+    /// The raw key
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+}
+
+#[cfg(any(feature = "mit", feature = "heimdal"))]
+unsafe fn session_key(ctx: gss_ctx_id_t) -> Result<SessionKey, Error> {
+    let mut parts = inquire_by_oid(ctx, &GSS_INQ_SSPI_SESSION_KEY)?;
+    if parts.len() < 2 {
+        parts.zero();
+        return Err(Error {
+            major: MajorFlags::GSS_S_FAILURE,
+            minor: 0,
+            detail: None,
+            mech: ctx_mech(ctx),
+        });
+    }
+    let enctype_oid = parts[1].to_vec();
+    let key = parts[0].to_vec();
+    parts.zero();
+    Ok(SessionKey { key, enctype_oid })
+}
+
+unsafe fn wrap_size_limit(
+    ctx: gss_ctx_id_t,
+    encrypt: bool,
+    qop: Qop,
+    output_size: OM_uint32,
+) -> Result<u32, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut max_input_size: OM_uint32 = 0;
+    let major = gss_wrap_size_limit(
+        &mut minor as *mut OM_uint32,
+        ctx,
+        if encrypt { 1 } else { 0 },
+        qop.0,
+        output_size,
+        &mut max_input_size as *mut OM_uint32,
+    );
+    if major == GSS_S_COMPLETE {
+        Ok(max_input_size)
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_unchecked(major),
+            minor,
+            detail: None,
+            mech: ctx_mech(ctx),
+        })
+    }
+}
+
+unsafe fn get_mic(ctx: gss_ctx_id_t, qop: Qop, msg: &[u8]) -> Result<Buf, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut msg = BufRef::from(msg);
+    let mut token = Buf::empty();
+    let major = gss_get_mic(
+        &mut minor as *mut OM_uint32,
+        ctx,
+        qop.0,
+        msg.to_c(),
+        token.to_c(),
+    );
+    if major == GSS_S_COMPLETE {
+        Ok(token)
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_unchecked(major),
+            minor,
+            detail: None,
+            mech: ctx_mech(ctx),
+        })
+    }
+}
+
+unsafe fn verify_mic(
+    ctx: gss_ctx_id_t,
+    msg: &[u8],
+    token: &[u8],
+    strict: bool,
+) -> Result<MicInfo, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut msg = BufRef::from(msg);
+    let mut token = BufRef::from(token);
+    let mut qop: OM_uint32 = 0;
+    let major = gss_verify_mic(
+        &mut minor as *mut OM_uint32,
+        ctx,
+        msg.to_c(),
+        token.to_c(),
+        &mut qop as *mut OM_uint32,
+    );
+    if let Some(supplementary) = supplementary(major, strict) {
+        Ok(MicInfo {
+            qop: Qop(qop),
+            supplementary,
+        })
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_unchecked(major),
+            minor,
+            detail: None,
+            mech: ctx_mech(ctx),
         })
     }
 }
@@ -165,6 +684,8 @@ unsafe fn unwrap_iov(ctx: gss_ctx_id_t, msg: &mut [GssIov]) -> Result<(), Error>
         Err(Error {
             major: MajorFlags::from_bits_unchecked(major),
             minor,
+            detail: None,
+            mech: ctx_mech(ctx),
         })
     }
 }
@@ -173,7 +694,8 @@ unsafe fn unwrap_iov(ctx: gss_ctx_id_t, msg: &mut [GssIov]) -> Result<(), Error>
 pub struct CtxInfo {
     pub source_name: Name,
     pub target_name: Name,
-    pub lifetime: Duration,
+    /// `None` if the context lifetime is indefinite
+    pub lifetime: Option<Duration>,
     pub mechanism: &'static Oid,
     pub flags: CtxFlags,
     pub local: bool,
@@ -249,6 +771,8 @@ unsafe fn info(ctx: gss_ctx_id_t, mut ifo: CtxInfoC) -> Result<CtxInfoC, Error>
         Err(Error {
             major: MajorFlags::from_bits_unchecked(major),
             minor,
+            detail: None,
+            mech: None,
         })
     } else {
         Ok(ifo)
@@ -271,7 +795,7 @@ unsafe fn full_info(ctx: gss_ctx_id_t) -> Result<CtxInfo, Error> {
     Ok(CtxInfo {
         source_name: Name::from_c(c.source_name.unwrap()),
         target_name: Name::from_c(c.target_name.unwrap()),
-        lifetime: Duration::from_secs(c.lifetime.unwrap() as u64),
+        lifetime: time_from_c(c.lifetime.unwrap()),
         mechanism: Oid::from_c(c.mechanism.unwrap()),
         flags: CtxFlags::from_bits_unchecked(c.flags.unwrap()),
         local: c.local.unwrap() > 0,
@@ -301,7 +825,7 @@ unsafe fn target_name(ctx: gss_ctx_id_t) -> Result<Name, Error> {
     Ok(Name::from_c(c.target_name.unwrap()))
 }
 
-unsafe fn lifetime(ctx: gss_ctx_id_t) -> Result<Duration, Error> {
+unsafe fn lifetime(ctx: gss_ctx_id_t) -> Result<Option<Duration>, Error> {
     let c = info(
         ctx,
         CtxInfoC {
@@ -309,7 +833,33 @@ unsafe fn lifetime(ctx: gss_ctx_id_t) -> Result<Duration, Error> {
             ..CtxInfoC::empty()
         },
     )?;
-    Ok(Duration::from_secs(c.lifetime.unwrap() as u64))
+    Ok(time_from_c(c.lifetime.unwrap()))
+}
+
+unsafe fn time_remaining(ctx: gss_ctx_id_t) -> Result<Option<Duration>, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut time: OM_uint32 = 0;
+    let major = gss_context_time(
+        &mut minor as *mut OM_uint32,
+        ctx,
+        &mut time as *mut OM_uint32,
+    );
+    if major == GSS_S_COMPLETE {
+        Ok(time_from_c(time))
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_unchecked(major),
+            minor,
+            detail: None,
+            mech: ctx_mech(ctx),
+        })
+    }
+}
+
+/// The mechanism of the context, used to interpret minor status
+/// codes when something goes wrong.
+unsafe fn ctx_mech(ctx: gss_ctx_id_t) -> Option<Oid> {
+    mechanism(ctx).ok().copied()
 }
 
 unsafe fn mechanism(ctx: gss_ctx_id_t) -> Result<&'static Oid, Error> {
@@ -356,6 +906,28 @@ unsafe fn open(ctx: gss_ctx_id_t) -> Result<bool, Error> {
     Ok(c.open.unwrap() > 0)
 }
 
+/// The operations shared by client and server contexts. The trait is
+/// object safe, so both kinds can be handled together, e.g. by code
+/// relaying messages between the two sides of a proxy. `delete`
+/// consumes the context, so it can't be called through a trait
+/// object, drop the box instead.
+///
+/// ```no_run
+/// # use libgssapi::{context::{ClientCtx, SecurityContext, ServerCtx}, error::Error};
+/// fn relay(
+///     from: &mut dyn SecurityContext,
+///     to: &mut dyn SecurityContext,
+///     msg: &[u8],
+/// ) -> Result<Vec<u8>, Error> {
+///     let plain = from.unwrap(msg)?;
+///     Ok(to.wrap(true, &plain)?.to_vec())
+/// }
+/// fn _assert(_: &dyn SecurityContext) {}
+/// fn _both(client: &ClientCtx, server: &ServerCtx) {
+///     _assert(client);
+///     _assert(server);
+/// }
+/// ```
 pub trait SecurityContext {
     /// Wrap a message with optional encryption. If `encrypt` is true
     /// then only the other side of the context can read the
@@ -363,6 +935,30 @@ pub trait SecurityContext {
     /// integrity.
     fn wrap(&mut self, encrypt: bool, msg: &[u8]) -> Result<Buf, Error>;
 
+    /// The same as `wrap`, except the message is protected with the
+    /// specified quality of protection instead of the default
+    /// one.
+    fn wrap_qop(&mut self, encrypt: bool, qop: Qop, msg: &[u8]) -> Result<Buf, Error>;
+
+    /// Wrap each of `msgs`, returning the wrapped messages in the
+    /// same order. Each result is the same token `wrap` would
+    /// produce. If any message fails to wrap the whole batch fails.
+    ///
+    /// The results are `Bytes` rather than `Buf` because a `Buf` has
+    /// to be allocated by gssapi, one per message. With the `iov`
+    /// feature `ClientCtx` and `ServerCtx` instead wrap the tokens in
+    /// place, with `gss_wrap_iov`, into a single buffer allocated up
+    /// front, and the returned `Bytes` share it. The token overhead is
+    /// asked for once per distinct message length, so batches of many
+    /// small messages of the same size are the best case (see
+    /// `benches/wrap_many.rs`). The default implementation just calls
+    /// `wrap` for each message and copies the result.
+    fn wrap_many(&mut self, encrypt: bool, msgs: &[&[u8]]) -> Result<Vec<Bytes>, Error> {
+        msgs.iter()
+            .map(|msg| Ok(Bytes::copy_from_slice(&self.wrap(encrypt, msg)?)))
+            .collect()
+    }
+
     /** From the MIT kerberos documentation,
 
     > Sign and optionally encrypt a sequence of buffers. The buffers
@@ -420,10 +1016,28 @@ pub trait SecurityContext {
         msg: &mut [GssIovFake],
     ) -> Result<(), Error>;
 
+    /// Return the largest message that can be passed to `wrap` (or
+    /// `wrap_qop`) with the same `encrypt` and `qop` such that the
+    /// resulting token is no larger than `output_size`. Use this to
+    /// chunk messages for protocols with a maximum record size.
+    fn wrap_size_limit(
+        &mut self,
+        encrypt: bool,
+        qop: Qop,
+        output_size: u32,
+    ) -> Result<u32, Error>;
+
     /// Unwrap a wrapped message, checking it's integrity and
     /// decrypting it if necessary.
     fn unwrap(&mut self, msg: &[u8]) -> Result<Buf, Error>;
 
+    /// The same as `unwrap`, but also return how the message was
+    /// protected, e.g. whether it was actually encrypted. Unlike
+    /// `unwrap`, a message that is valid but arrived out of order or
+    /// duplicated is returned with the `supplementary` flags set,
+    /// rather than as an error, so it's up to you to check them.
+    fn unwrap_info(&mut self, msg: &[u8]) -> Result<(Buf, UnwrapInfo), Error>;
+
     /** From the MIT Kerberos documentation,
 
     > gss_unwrap_iov may be called with an IOV list just like one which
@@ -443,6 +1057,100 @@ pub trait SecurityContext {
     #[cfg(feature = "iov")]
     fn unwrap_iov(&mut self, msg: &mut [GssIov]) -> Result<(), Error>;
 
+    /// Compute a message integrity code (MIC) for `msg` that the
+    /// other side can check with `verify_mic`. Unlike `wrap` the
+    /// message itself isn't included, so you must send it along with
+    /// the MIC.
+    fn get_mic(&mut self, msg: &[u8]) -> Result<Buf, Error>;
+
+    /// The same as `get_mic`, except the MIC is computed with the
+    /// specified quality of protection instead of the default one.
+    fn get_mic_qop(&mut self, qop: Qop, msg: &[u8]) -> Result<Buf, Error>;
+
+    /// Verify that `token` is a valid MIC for `msg`, returning the
+    /// quality of protection that was used by the sender. An invalid
+    /// MIC is reported as `GSS_S_BAD_SIG`, while a malformed token is
+    /// reported as `GSS_S_DEFECTIVE_TOKEN`. If you require a
+    /// particular quality of protection check the returned value, a
+    /// MIC computed with a weaker one still verifies.
+    fn verify_mic(&mut self, msg: &[u8], token: &[u8]) -> Result<Qop, Error>;
+
+    /// The same as `verify_mic`, except a valid MIC for a message that
+    /// arrived out of order or duplicated is returned with the
+    /// `supplementary` flags set, rather than as an error.
+    fn verify_mic_info(&mut self, msg: &[u8], token: &[u8]) -> Result<MicInfo, Error>;
+
+    /// Derive `desired_output_len` bytes of keying material from the
+    /// security context and `input` using the mechanism's pseudo
+    /// random function (RFC 4401). Both sides of the context will
+    /// derive the same bytes from the same `prf_key` and `input`. If
+    /// the mechanism can't produce that much output an error is
+    /// returned, the output is never truncated.
+    fn pseudo_random(
+        &mut self,
+        prf_key: PrfKey,
+        input: &[u8],
+        desired_output_len: usize,
+    ) -> Result<Buf, Error>;
+
+    /// Set the mechanism specific option `desired_object` on the
+    /// security context. The meaning of `value` depends on the
+    /// option. Returns false if the mechanism doesn't support the
+    /// option (`GSS_S_UNAVAILABLE`). The mechanism may replace the
+    /// underlying context handle, in which case this context is
+    /// updated to refer to the new one.
+    fn set_option(&mut self, desired_object: &Oid, value: &[u8]) -> Result<bool, Error>;
+
+    /// Ask the mechanism for the data identified by `oid`, e.g. one
+    /// of the `GSS_INQ_*` oids, or a vendor specific one. The format
+    /// of the result depends on the oid.
+    fn inquire_by_oid(&mut self, oid: &Oid) -> Result<BufferSet, Error>;
+
+    /// Get the raw session key of the security context, e.g. for SSH
+    /// key exchange. This is only supported by the MIT and Heimdal
+    /// krb5 mechanisms, others fail with `GSS_S_UNAVAILABLE`, so
+    /// it's only available with the `mit` or `heimdal` feature.
+    #[cfg(any(feature = "mit", feature = "heimdal"))]
+    fn session_key(&mut self) -> Result<SessionKey, Error>;
+
+    /// Process a token sent by the peer outside of context
+    /// establishment, such as the token produced when the peer deletes
+    /// its context. After processing a deletion token the context
+    /// can't be used any more.
+    fn process_token(&mut self, token: &[u8]) -> Result<(), Error>;
+
+    /// Delete the security context, returning the token, if any, the
+    /// mechanism wants sent to the peer so it can delete its side with
+    /// `process_token`. Dropping a context deletes it without
+    /// producing a token. Most mechanisms, including krb5, never
+    /// produce one.
+    fn delete(self) -> Result<Option<Buf>, Error>;
+
+    /// Return true if this context and `other` were established
+    /// between the same source and target names with the same
+    /// mechanism, e.g. to decide whether a pooled connection can be
+    /// reused. The names are canonicalized for the mechanism before
+    /// being compared.
+    fn same_peer(&mut self, other: &mut dyn SecurityContext) -> Result<bool, Error> {
+        let mech = self.mechanism()?;
+        if mech != other.mechanism()? {
+            return Ok(false);
+        }
+        let same = |a: Name, b: Name| -> Result<bool, Error> {
+            a.canonicalize(Some(mech))?
+                .compare(&b.canonicalize(Some(mech))?)
+        };
+        Ok(same(self.source_name()?, other.source_name()?)?
+            && same(self.target_name()?, other.target_name()?)?)
+    }
+
+    /// Export this fully established security context into a token
+    /// that can be passed to another process and imported there with
+    /// `ServerCtx::import`. This consumes the underlying gssapi
+    /// context, after a successful export this context can't be
+    /// used any more.
+    fn export(&mut self) -> Result<Buf, Error>;
+
     /// Get all information about a security context in one call
     fn info(&mut self) -> Result<CtxInfo, Error>;
 
@@ -452,13 +1160,27 @@ pub trait SecurityContext {
     /// Get the target name of the security context
     fn target_name(&mut self) -> Result<Name, Error>;
 
-    /// Get the lifetime of the security context
-    fn lifetime(&mut self) -> Result<Duration, Error>;
-
-    /// Get the mechanism of the security context
+    /// Get the lifetime of the security context, `None` if it is
+    /// indefinite
+    fn lifetime(&mut self) -> Result<Option<Duration>, Error>;
+
+    /// Get the time remaining before the security context expires,
+    /// `None` if it is indefinite. An already expired context is
+    /// reported as an error with `GSS_S_CONTEXT_EXPIRED` set, rather
+    /// than as a zero duration.
+    fn time_remaining(&mut self) -> Result<Option<Duration>, Error>;
+
+    /// Get the mechanism of the security context. For a context
+    /// established with a pseudo mechanism like `GSS_MECH_SPNEGO`
+    /// this is the mechanism that was actually negotiated,
+    /// e.g. `GSS_MECH_KRB5`, since gssapi inquires the underlying
+    /// context. That may not be known until the context is complete.
     fn mechanism(&mut self) -> Result<&'static Oid, Error>;
 
-    /// Get the flags of the security context
+    /// Get the flags of the security context. These are the flags
+    /// the mechanism actually granted, which may differ from the ones
+    /// requested, e.g. `GSS_C_DELEG_FLAG` is only set if credentials
+    /// were delegated.
     fn flags(&mut self) -> Result<CtxFlags, Error>;
 
     /// Return true if the security context was locally initiated
@@ -471,6 +1193,96 @@ pub trait SecurityContext {
     fn is_complete(&self) -> bool;
 }
 
+/// Channel bindings tie a security context to the underlying
+/// transport, e.g. a TLS channel (RFC 5929), so a man in the middle
+/// can't relay the context establishment tokens. Both sides must use
+/// the same bindings, otherwise the acceptor fails with
+/// `GSS_S_BAD_BINDINGS`.
+///
+/// Most protocols set only `application_data` and leave the addresses
+/// empty, which is what `ChannelBindings::new` does.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChannelBindings {
+    /// one of the `GSS_C_AF_*` address families
+    pub initiator_addrtype: u32,
+    pub initiator_address: Vec<u8>,
+    /// one of the `GSS_C_AF_*` address families
+    pub acceptor_addrtype: u32,
+    pub acceptor_address: Vec<u8>,
+    pub application_data: Vec<u8>,
+}
+
+impl ChannelBindings {
+    /// Create channel bindings with just application data, e.g. for
+    /// tls-server-end-point or tls-unique bindings.
+    pub fn new(application_data: &[u8]) -> ChannelBindings {
+        ChannelBindings {
+            application_data: Vec::from(application_data),
+            ..ChannelBindings::default()
+        }
+    }
+
+    /// The returned struct points into self, so it must not outlive
+    /// it.
+    pub(crate) unsafe fn to_c(&self) -> gss_channel_bindings_struct {
+        #[inline]
+        fn buffer(b: &[u8]) -> gss_buffer_desc {
+            gss_buffer_desc {
+                length: b.len() as size_t,
+                value: b.as_ptr() as *mut ffi::c_void,
+            }
+        }
+        gss_channel_bindings_struct {
+            initiator_addrtype: self.initiator_addrtype,
+            initiator_address: buffer(&self.initiator_address),
+            acceptor_addrtype: self.acceptor_addrtype,
+            acceptor_address: buffer(&self.acceptor_address),
+            application_data: buffer(&self.application_data),
+        }
+    }
+}
+
+/// An exported security context token, as produced by
+/// `SecurityContext::export`, suitable for storing. With the `serde`
+/// feature this is serializable, deserializing it only checks that it
+/// is well formed serde data, the context isn't imported until
+/// `import` is called.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", rename = "gss_exported_context")
+)]
+pub struct ExportedContext {
+    token: Vec<u8>,
+}
+
+impl From<Vec<u8>> for ExportedContext {
+    fn from(token: Vec<u8>) -> Self {
+        ExportedContext { token }
+    }
+}
+
+impl ExportedContext {
+    /// Export `ctx`, after which it can't be used any more, see
+    /// `SecurityContext::export`.
+    pub fn new<C: SecurityContext + ?Sized>(ctx: &mut C) -> Result<Self, Error> {
+        Ok(ExportedContext {
+            token: ctx.export()?.to_vec(),
+        })
+    }
+
+    /// The raw exported context token
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.token
+    }
+
+    /// Import the context, see `ServerCtx::import`
+    pub fn import(&self) -> Result<ServerCtx, Error> {
+        ServerCtx::import(&self.token)
+    }
+}
+
 #[derive(Debug)]
 enum ServerCtxState {
     Uninitialized,
@@ -479,11 +1291,24 @@ enum ServerCtxState {
     Complete,
 }
 
+// The authorization data inquiry oids, the ad_type is appended
+// to these as the last component, base 128 encoded.
+#[cfg(any(feature = "mit", feature = "heimdal"))]
+const AUTHZ_DATA_OID_PREFIXES: &[&[u8]] = &[
+    // GSS_KRB5_EXTRACT_AUTHZ_DATA_FROM_SEC_CONTEXT_OID 1.2.840.113554.1.2.2.5.10
+    #[cfg(feature = "mit")]
+    b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x02\x05\x0a",
+    // GSS_KRB5_EXTRACT_AUTHZ_DATA_FROM_SEC_CONTEXT_X 1.2.752.43.13.3
+    #[cfg(feature = "heimdal")]
+    b"\x2a\x85\x70\x2b\x0d\x03",
+];
+
 /// The server side of a security context
 #[derive(Debug)]
 pub struct ServerCtx {
     ctx: gss_ctx_id_t,
-    cred: Cred,
+    cred: Option<Cred>,
+    channel_bindings: Option<ChannelBindings>,
     delegated_cred: Option<Cred>,
     flags: CtxFlags,
     state: ServerCtxState,
@@ -500,29 +1325,141 @@ unsafe impl Sync for ServerCtx {}
 
 impl ServerCtx {
     /// Create a new uninitialized server context with the specified
-    /// credentials. You must then call `step` until the context is
-    /// fully initialized. The mechanism is not specified because it
-    /// is dictated by the client.
-    pub fn new(cred: Cred) -> ServerCtx {
+    /// credentials, or the default acceptor credentials (e.g. the
+    /// keytab) if `cred` is `None`. If the connection uses channel
+    /// bindings then the client must use the same ones. You must then
+    /// call `step` until the context is fully initialized. The
+    /// mechanism is not specified because it is dictated by the
+    /// client.
+    pub fn new(
+        cred: Option<Cred>,
+        channel_bindings: Option<&ChannelBindings>,
+    ) -> ServerCtx {
         ServerCtx {
             ctx: ptr::null_mut(),
             cred,
+            channel_bindings: channel_bindings.cloned(),
             delegated_cred: None,
             flags: CtxFlags::empty(),
             state: ServerCtxState::Uninitialized,
         }
     }
 
+    /// Import a fully established security context from a token
+    /// produced by `SecurityContext::export` in another process. This
+    /// works for contexts exported from either side, the imported
+    /// context carries on with the same state (e.g. sequence numbers)
+    /// as the exported one.
+    pub fn import(token: &[u8]) -> Result<ServerCtx, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut token = BufRef::from(token);
+        let mut ctx = ptr::null_mut::<gss_ctx_id_struct>();
+        let major = unsafe {
+            gss_import_sec_context(
+                &mut minor as *mut OM_uint32,
+                token.to_c(),
+                &mut ctx as *mut gss_ctx_id_t,
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            let flags = unsafe { flags(ctx) }.unwrap_or(CtxFlags::empty());
+            Ok(ServerCtx {
+                ctx,
+                cred: None,
+                channel_bindings: None,
+                delegated_cred: None,
+                flags,
+                state: ServerCtxState::Complete,
+            })
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: None,
+            })
+        }
+    }
+
+    /// Get the Kerberos authorization data of type `ad_type` from
+    /// the client's ticket, e.g. 128 for the Windows PAC
+    /// (AD-WIN2K-PAC). The type becomes the last component of the
+    /// inquiry oid, so it can't be negative, which rules out the
+    /// types reserved for local use. The data is returned undecoded. This only works
+    /// with the krb5 mechanism, if the authorization data isn't
+    /// present, or the mechanism isn't krb5, an error is returned.
+    ///
+    /// MIT and Heimdal use different inquiry oids for this, it's only
+    /// available if the `mit` or `heimdal` feature is enabled. If both
+    /// are, the MIT oid is tried first.
+    #[cfg(any(feature = "mit", feature = "heimdal"))]
+    pub fn authz_data(&mut self, ad_type: u32) -> Result<Vec<u8>, Error> {
+        let mut component = Vec::new();
+        let mut v = ad_type;
+        loop {
+            let more = if component.is_empty() { 0 } else { 0x80 };
+            component.push((v & 0x7f) as u8 | more);
+            v >>= 7;
+            if v == 0 {
+                break;
+            }
+        }
+        let mut err = Error {
+            major: MajorFlags::GSS_S_UNAVAILABLE,
+            minor: 0,
+            detail: None,
+            mech: unsafe { ctx_mech(self.ctx) },
+        };
+        for prefix in AUTHZ_DATA_OID_PREFIXES {
+            let mut ber = Vec::from(*prefix);
+            ber.extend(component.iter().rev());
+            let oid = Oid::from(gss_OID_desc {
+                length: ber.len() as OM_uint32,
+                elements: ber.as_mut_ptr() as *mut ffi::c_void,
+            });
+            match unsafe { inquire_by_oid(self.ctx, &oid) } {
+                Ok(data) if !data.is_empty() => return Ok(data[0].to_vec()),
+                Ok(_) => (),
+                Err(e) => err = e,
+            }
+        }
+        Err(err)
+    }
+
+    /// Get the credential the client delegated to us during context
+    /// establishment, if it requested delegation
+    /// (`GSS_C_DELEG_FLAG`). The returned credential can be used to
+    /// act on the client's behalf. It shares its handle with the one
+    /// kept by the context, so every call returns the same
+    /// credential.
+    pub fn delegated_cred(&self) -> Option<Cred> {
+        self.delegated_cred.clone()
+    }
+
+    /// Get the client's name exported for direct comparison, e.g. to
+    /// store in an ACL. Not every mechanism returns a mechanism name
+    /// from `source_name`, and exporting one that isn't fails with
+    /// `GSS_S_NAME_NOT_MN`, so the name is canonicalized for the
+    /// context's mechanism first.
+    pub fn source_name_exported(&mut self) -> Result<Buf, Error> {
+        let mech = self.mechanism()?;
+        self.source_name()?.canonicalize(Some(mech))?.export()
+    }
+
     /// Perform 1 step in the initialization of the server context,
-    /// feeding it a token you received from the client. If
-    /// initialization is complete from the point of view of the
-    /// server then this will return Ok(None). Otherwise it will
-    /// return a token that needs to be sent to the client and fed to
-    /// `ClientCtx::step`.
-    pub fn step(&mut self, tok: &[u8]) -> Result<Option<Buf>, Error> {
+    /// feeding it a token you received from the client. If a token is
+    /// returned it needs to be sent to the client and fed to
+    /// `ClientCtx::step`. The final step may return a token as well
+    /// (e.g. with mutual authentication), so check `is_complete`
+    /// after each step rather than waiting for `Ok(None)`. If the
+    /// step fails the error may carry a token, which should be sent
+    /// to the client so it learns why establishment failed.
+    pub fn step(&mut self, tok: &[u8]) -> Result<Option<Buf>, StepError> {
         match self.state {
             ServerCtxState::Uninitialized | ServerCtxState::Partial => (),
-            ServerCtxState::Failed(e) => return Err(e),
+            ServerCtxState::Failed(error) => {
+                return Err(StepError { error, token: None })
+            }
             ServerCtxState::Complete => return Ok(None),
         }
         let mut minor = GSS_S_COMPLETE;
@@ -530,15 +1467,26 @@ impl ServerCtx {
         let mut out_tok = Buf::empty();
         let mut delegated_cred = ptr::null_mut::<gss_cred_id_struct>();
         let mut flag_bits: u32 = 0;
+        let mut mech = ptr::null_mut::<gss_OID_desc>();
+        let mut cbs = self
+            .channel_bindings
+            .as_ref()
+            .map(|cb| unsafe { cb.to_c() });
         let major = unsafe {
             gss_accept_sec_context(
                 &mut minor as *mut OM_uint32,
                 &mut self.ctx as *mut gss_ctx_id_t,
-                self.cred.to_c(),
+                match self.cred {
+                    None => ptr::null_mut::<gss_cred_id_struct>(),
+                    Some(ref cred) => cred.to_c(),
+                },
                 tok.to_c(),
-                ptr::null_mut::<gss_channel_bindings_struct>(),
+                match cbs {
+                    None => ptr::null_mut::<gss_channel_bindings_struct>(),
+                    Some(ref mut cbs) => cbs as gss_channel_bindings_t,
+                },
                 ptr::null_mut::<gss_name_t>(),
-                ptr::null_mut::<gss_OID>(),
+                &mut mech as *mut gss_OID,
                 out_tok.to_c(),
                 &mut flag_bits as *mut OM_uint32,
                 ptr::null_mut::<OM_uint32>(),
@@ -557,16 +1505,30 @@ impl ServerCtx {
                 },
             }
         }
-        if let Some(new_flags) = CtxFlags::from_bits(flag_bits) {
-            self.flags.insert(new_flags);
-        }
+        self.flags.insert(CtxFlags::from_bits_truncate(flag_bits));
         if gss_error(major) > 0 {
+            // the mech is needed to resolve the minor status into a
+            // message, if accept didn't report it ask the partially
+            // established context instead
             let e = Error {
                 major: unsafe { MajorFlags::from_bits_unchecked(major) },
                 minor,
+                detail: None,
+                mech: if mech.is_null() {
+                    unsafe { ctx_mech(self.ctx) }
+                } else {
+                    Some(unsafe { *Oid::from_c(mech) })
+                },
             };
             self.state = ServerCtxState::Failed(e);
-            Err(e)
+            Err(StepError {
+                error: e,
+                token: if out_tok.is_empty() {
+                    None
+                } else {
+                    Some(out_tok)
+                },
+            })
         } else if major & _GSS_S_CONTINUE_NEEDED > 0 {
             self.state = ServerCtxState::Partial;
             Ok(Some(out_tok))
@@ -583,7 +1545,16 @@ impl ServerCtx {
 
 impl SecurityContext for ServerCtx {
     fn wrap(&mut self, encrypt: bool, msg: &[u8]) -> Result<Buf, Error> {
-        unsafe { wrap(self.ctx, encrypt, msg) }
+        unsafe { wrap(self.ctx, encrypt, Qop::DEFAULT, msg) }
+    }
+
+    fn wrap_qop(&mut self, encrypt: bool, qop: Qop, msg: &[u8]) -> Result<Buf, Error> {
+        unsafe { wrap(self.ctx, encrypt, qop, msg) }
+    }
+
+    #[cfg(feature = "iov")]
+    fn wrap_many(&mut self, encrypt: bool, msgs: &[&[u8]]) -> Result<Vec<Bytes>, Error> {
+        unsafe { wrap_many(self.ctx, encrypt, msgs) }
     }
 
     #[cfg(feature = "iov")]
@@ -600,8 +1571,21 @@ impl SecurityContext for ServerCtx {
         unsafe { wrap_iov_length(self.ctx, encrypt, msg) }
     }
 
+    fn wrap_size_limit(
+        &mut self,
+        encrypt: bool,
+        qop: Qop,
+        output_size: u32,
+    ) -> Result<u32, Error> {
+        unsafe { wrap_size_limit(self.ctx, encrypt, qop, output_size) }
+    }
+
     fn unwrap(&mut self, msg: &[u8]) -> Result<Buf, Error> {
-        unsafe { unwrap(self.ctx, msg) }
+        unsafe { unwrap(self.ctx, msg, true).map(|(msg, _)| msg) }
+    }
+
+    fn unwrap_info(&mut self, msg: &[u8]) -> Result<(Buf, UnwrapInfo), Error> {
+        unsafe { unwrap(self.ctx, msg, false) }
     }
 
     #[cfg(feature = "iov")]
@@ -609,6 +1593,56 @@ impl SecurityContext for ServerCtx {
         unsafe { unwrap_iov(self.ctx, msg) }
     }
 
+    fn get_mic(&mut self, msg: &[u8]) -> Result<Buf, Error> {
+        unsafe { get_mic(self.ctx, Qop::DEFAULT, msg) }
+    }
+
+    fn get_mic_qop(&mut self, qop: Qop, msg: &[u8]) -> Result<Buf, Error> {
+        unsafe { get_mic(self.ctx, qop, msg) }
+    }
+
+    fn verify_mic(&mut self, msg: &[u8], token: &[u8]) -> Result<Qop, Error> {
+        unsafe { verify_mic(self.ctx, msg, token, true).map(|ifo| ifo.qop) }
+    }
+
+    fn verify_mic_info(&mut self, msg: &[u8], token: &[u8]) -> Result<MicInfo, Error> {
+        unsafe { verify_mic(self.ctx, msg, token, false) }
+    }
+
+    fn pseudo_random(
+        &mut self,
+        prf_key: PrfKey,
+        input: &[u8],
+        desired_output_len: usize,
+    ) -> Result<Buf, Error> {
+        unsafe { pseudo_random(self.ctx, prf_key, input, desired_output_len) }
+    }
+
+    fn set_option(&mut self, desired_object: &Oid, value: &[u8]) -> Result<bool, Error> {
+        unsafe { set_option(&mut self.ctx, desired_object, value) }
+    }
+
+    fn inquire_by_oid(&mut self, oid: &Oid) -> Result<BufferSet, Error> {
+        unsafe { inquire_by_oid(self.ctx, oid) }
+    }
+
+    #[cfg(any(feature = "mit", feature = "heimdal"))]
+    fn session_key(&mut self) -> Result<SessionKey, Error> {
+        unsafe { session_key(self.ctx) }
+    }
+
+    fn process_token(&mut self, token: &[u8]) -> Result<(), Error> {
+        unsafe { process_token(self.ctx, token) }
+    }
+
+    fn delete(mut self) -> Result<Option<Buf>, Error> {
+        unsafe { delete(&mut self.ctx) }
+    }
+
+    fn export(&mut self) -> Result<Buf, Error> {
+        unsafe { export(&mut self.ctx) }
+    }
+
     fn info(&mut self) -> Result<CtxInfo, Error> {
         unsafe { full_info(self.ctx) }
     }
@@ -621,10 +1655,14 @@ impl SecurityContext for ServerCtx {
         unsafe { target_name(self.ctx) }
     }
 
-    fn lifetime(&mut self) -> Result<Duration, Error> {
+    fn lifetime(&mut self) -> Result<Option<Duration>, Error> {
         unsafe { lifetime(self.ctx) }
     }
 
+    fn time_remaining(&mut self) -> Result<Option<Duration>, Error> {
+        unsafe { time_remaining(self.ctx) }
+    }
+
     fn mechanism(&mut self) -> Result<&'static Oid, Error> {
         unsafe { mechanism(self.ctx) }
     }
@@ -668,6 +1706,8 @@ pub struct ClientCtx {
     flags: CtxFlags,
     state: ClientCtxState,
     mech: Option<&'static Oid>,
+    time_req: Option<Duration>,
+    channel_bindings: Option<ChannelBindings>,
 }
 
 impl Drop for ClientCtx {
@@ -679,17 +1719,26 @@ impl Drop for ClientCtx {
 unsafe impl Send for ClientCtx {}
 unsafe impl Sync for ClientCtx {}
 
+// the longest `ClientCtx::new_with_retry` will sleep between attempts
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+
+fn next_backoff(backoff: Duration) -> Duration {
+    backoff.saturating_mul(2).min(MAX_RETRY_BACKOFF)
+}
+
 impl ClientCtx {
     /// Create a new uninitialized client security context using the
     /// specified credentials, targeting the service named by target,
     /// and optionally using a specific mechanism (otherwise gssapi
-    /// will pick a default for you). To finish initializing the
-    /// context you must call `step`.
+    /// will pick a default for you). If the connection uses channel
+    /// bindings then the server must use the same ones. To finish
+    /// initializing the context you must call `step`.
     pub fn new(
         cred: Cred,
         target: Name,
         flags: CtxFlags,
         mech: Option<&'static Oid>,
+        channel_bindings: Option<&ChannelBindings>,
     ) -> ClientCtx {
         ClientCtx {
             ctx: ptr::null_mut(),
@@ -698,56 +1747,92 @@ impl ClientCtx {
             flags,
             state: ClientCtxState::Uninitialized,
             mech,
+            time_req: None,
+            channel_bindings: channel_bindings.cloned(),
+        }
+    }
+
+    /// Start building a client security context targeting the service
+    /// named by `target`, see `ClientCtxBuilder`.
+    pub fn builder(target: Name) -> ClientCtxBuilder {
+        ClientCtxBuilder::new(target)
+    }
+
+    /// The same as `new`, followed by the first `step`, except that if
+    /// the first step fails with a transient error (see
+    /// `Error::is_transient`, e.g. the KDC could not be reached) it is
+    /// retried up to `retries` times. The thread sleeps for `backoff`
+    /// before the first retry, doubling it before each subsequent
+    /// one, but never sleeps for more than a minute at a time.
+    /// Permanent errors are returned immediately. As with `step`, the
+    /// error token of the last attempt, if any, is in the `StepError`.
+    ///
+    /// This blocks the calling thread, both in gssapi and while
+    /// sleeping between retries, so don't call it directly from async
+    /// code, e.g. with tokio run it with `spawn_blocking`.
+    pub fn new_with_retry(
+        cred: Cred,
+        target: Name,
+        flags: CtxFlags,
+        mech: Option<&'static Oid>,
+        channel_bindings: Option<&ChannelBindings>,
+        retries: u32,
+        backoff: Duration,
+    ) -> Result<(ClientCtx, Option<Buf>), StepError> {
+        let mut ctx = ClientCtx::new(cred, target, flags, mech, channel_bindings);
+        let mut backoff = backoff.min(MAX_RETRY_BACKOFF);
+        let mut attempt = 0;
+        loop {
+            match ctx.step(None) {
+                Ok(tok) => return Ok((ctx, tok)),
+                Err(e) if attempt < retries && e.error.is_transient() => {
+                    ctx.reset();
+                    thread::sleep(backoff);
+                    backoff = next_backoff(backoff);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
         }
     }
 
+    // return a failed context to the uninitialized state so
+    // establishment can start over
+    fn reset(&mut self) {
+        delete_ctx(self.ctx);
+        self.ctx = ptr::null_mut();
+        self.state = ClientCtxState::Uninitialized;
+    }
+
     /// Perform 1 step in the initialization of the specfied security
     /// context. Since the client initiates context creation, the
-    /// token will initially be None. If the connection uses channel
-    /// bindings, they are passed as the second argument.
+    /// token will initially be None.
     ///
     /// As a result this step, GSSAPI will give you a token
     /// to send to the server. The server may send back a token, which
     /// you must feed to this function, and possibly get another token
     /// to send to the server. This will go on a mechanism specifiec
-    /// number of times until step returns `Ok(None)`. At that point
-    /// the context is fully initialized.
-    pub fn step(
-        &mut self,
-        tok: Option<&[u8]>,
-        channel_bindings: Option<&[u8]>,
-    ) -> Result<Option<Buf>, Error> {
-        #[inline]
-        fn empty_buffer() -> gss_buffer_desc {
-            gss_buffer_desc {
-                length: 0,
-                value: ptr::null_mut(),
-            }
-        }
+    /// number of times until `is_complete` returns true. At that point
+    /// the context is fully initialized, but the final step may still
+    /// have returned a token that must be sent to the server. If the
+    /// step fails the error may carry a token, which should be sent
+    /// to the server so it learns why establishment failed.
+    pub fn step(&mut self, tok: Option<&[u8]>) -> Result<Option<Buf>, StepError> {
         match self.state {
             ClientCtxState::Uninitialized | ClientCtxState::Partial => (),
-            ClientCtxState::Failed(e) => return Err(e),
+            ClientCtxState::Failed(error) => {
+                return Err(StepError { error, token: None })
+            }
             ClientCtxState::Complete => return Ok(None),
         };
-        let mut cbs = gss_channel_bindings_struct {
-            initiator_addrtype: 0,
-            initiator_address: empty_buffer(),
-            acceptor_addrtype: 0,
-            acceptor_address: empty_buffer(),
-            application_data: empty_buffer(),
-        };
-        let bindings = if let Some(cb) = channel_bindings {
-            cbs.application_data = gss_buffer_desc {
-                length: cb.len() as size_t,
-                value: cb.as_ptr() as *mut ffi::c_void,
-            };
-            &mut cbs as gss_channel_bindings_t
-        } else {
-            ptr::null_mut::<gss_channel_bindings_struct>()
-        };
+        let mut cbs = self
+            .channel_bindings
+            .as_ref()
+            .map(|cb| unsafe { cb.to_c() });
         let mut minor = GSS_S_COMPLETE;
         let mut tok = tok.map(BufRef::from);
         let mut out_tok = Buf::empty();
+        let mut mech = ptr::null_mut::<gss_OID_desc>();
         let major = unsafe {
             gss_init_sec_context(
                 &mut minor as *mut OM_uint32,
@@ -759,25 +1844,44 @@ impl ClientCtx {
                     Some(mech) => mech.to_c(),
                 },
                 self.flags.bits(),
-                _GSS_C_INDEFINITE,
-                bindings,
+                time_to_c(self.time_req),
+                match cbs {
+                    None => ptr::null_mut::<gss_channel_bindings_struct>(),
+                    Some(ref mut cbs) => cbs as gss_channel_bindings_t,
+                },
                 match tok {
                     None => ptr::null_mut::<gss_buffer_desc>(),
                     Some(ref mut tok) => tok.to_c(),
                 },
-                ptr::null_mut::<gss_OID>(),
+                &mut mech as *mut gss_OID,
                 out_tok.to_c(),
                 ptr::null_mut::<OM_uint32>(),
                 ptr::null_mut::<OM_uint32>(),
             )
         };
         if gss_error(major) > 0 {
+            // as in `ServerCtx::step`, prefer the mech gssapi actually
+            // used, which may not be the one we asked for (or we may
+            // not have asked for one)
             let e = Error {
                 major: unsafe { MajorFlags::from_bits_unchecked(major) },
                 minor,
+                detail: None,
+                mech: if !mech.is_null() {
+                    Some(unsafe { *Oid::from_c(mech) })
+                } else {
+                    unsafe { ctx_mech(self.ctx) }.or_else(|| self.mech.copied())
+                },
             };
             self.state = ClientCtxState::Failed(e);
-            Err(e)
+            Err(StepError {
+                error: e,
+                token: if out_tok.is_empty() {
+                    None
+                } else {
+                    Some(out_tok)
+                },
+            })
         } else if major & _GSS_S_CONTINUE_NEEDED > 0 {
             self.state = ClientCtxState::Partial;
             Ok(Some(out_tok))
@@ -792,9 +1896,141 @@ impl ClientCtx {
     }
 }
 
+/// Pick the first mechanism in `preferred` that both `cred` and
+/// `target` can be used with. If there is none fail with
+/// `ErrorDetail::NoCommonMechanism`.
+pub fn negotiate_mech(
+    cred: &Cred,
+    target: &Name,
+    preferred: &[Oid],
+) -> Result<Oid, Error> {
+    let cred_mechs = cred.mechanisms()?;
+    let name_mechs = target.mechanisms()?;
+    for mech in preferred {
+        if cred_mechs.contains(mech)? && name_mechs.contains(mech)? {
+            return Ok(*mech);
+        }
+    }
+    Err(Error {
+        major: MajorFlags::GSS_S_BAD_MECH,
+        minor: 0,
+        detail: Some(ErrorDetail::NoCommonMechanism),
+        mech: None,
+    })
+}
+
+/// A builder for client security contexts. By default the context
+/// uses the default initiator credentials, the default mechanism, no
+/// channel bindings, `GSS_C_MUTUAL_FLAG | GSS_C_SEQUENCE_FLAG`, and
+/// asks for the longest possible lifetime. e.g.
+///
+/// ```no_run
+/// # use libgssapi::{name::Name, context::ClientCtx};
+/// let target = Name::hostbased_service("nfs", "server")?;
+/// let (ctx, token) = ClientCtx::builder(target).build()?;
+/// # Ok::<(), libgssapi::error::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct ClientCtxBuilder {
+    cred: Option<Cred>,
+    target: Name,
+    flags: CtxFlags,
+    mech: Option<&'static Oid>,
+    time_req: Option<Duration>,
+    channel_bindings: Option<ChannelBindings>,
+}
+
+impl ClientCtxBuilder {
+    /// Create a builder for a context targeting the service named by
+    /// `target`
+    pub fn new(target: Name) -> ClientCtxBuilder {
+        ClientCtxBuilder {
+            cred: None,
+            target,
+            flags: CtxFlags::GSS_C_MUTUAL_FLAG | CtxFlags::GSS_C_SEQUENCE_FLAG,
+            mech: None,
+            time_req: None,
+            channel_bindings: None,
+        }
+    }
+
+    /// Use `cred` instead of the default initiator credentials
+    pub fn cred(mut self, cred: Cred) -> ClientCtxBuilder {
+        self.cred = Some(cred);
+        self
+    }
+
+    /// Target the service named by `target`
+    pub fn target(mut self, target: Name) -> ClientCtxBuilder {
+        self.target = target;
+        self
+    }
+
+    /// Use the mechanism `mech` instead of letting gssapi pick one
+    pub fn mech(mut self, mech: &'static Oid) -> ClientCtxBuilder {
+        self.mech = Some(mech);
+        self
+    }
+
+    /// Request `flags` instead of the defaults
+    pub fn flags(mut self, flags: CtxFlags) -> ClientCtxBuilder {
+        self.flags = flags;
+        self
+    }
+
+    /// Ask for the context to last `lifetime`. The mechanism may
+    /// grant less, check `SecurityContext::lifetime`.
+    pub fn lifetime(mut self, lifetime: Duration) -> ClientCtxBuilder {
+        self.time_req = Some(lifetime);
+        self
+    }
+
+    /// Use `channel_bindings`, the server must use the same ones
+    pub fn channel_bindings(
+        mut self,
+        channel_bindings: ChannelBindings,
+    ) -> ClientCtxBuilder {
+        self.channel_bindings = Some(channel_bindings);
+        self
+    }
+
+    /// Build the context and perform the first `step`, returning the
+    /// context and the token, if any, to send to the server. As with
+    /// `step`, if the first step fails the error token, if any, is in
+    /// the `StepError`. Failing to acquire the default credentials
+    /// has no token.
+    pub fn build(self) -> Result<(ClientCtx, Option<Buf>), StepError> {
+        let cred = match self.cred {
+            Some(cred) => cred,
+            None => Cred::acquire(None, None, CredUsage::Initiate, None)?,
+        };
+        let mut ctx = ClientCtx {
+            ctx: ptr::null_mut(),
+            cred,
+            target: self.target,
+            flags: self.flags,
+            state: ClientCtxState::Uninitialized,
+            mech: self.mech,
+            time_req: self.time_req,
+            channel_bindings: self.channel_bindings,
+        };
+        let token = ctx.step(None)?;
+        Ok((ctx, token))
+    }
+}
+
 impl SecurityContext for ClientCtx {
     fn wrap(&mut self, encrypt: bool, msg: &[u8]) -> Result<Buf, Error> {
-        unsafe { wrap(self.ctx, encrypt, msg) }
+        unsafe { wrap(self.ctx, encrypt, Qop::DEFAULT, msg) }
+    }
+
+    fn wrap_qop(&mut self, encrypt: bool, qop: Qop, msg: &[u8]) -> Result<Buf, Error> {
+        unsafe { wrap(self.ctx, encrypt, qop, msg) }
+    }
+
+    #[cfg(feature = "iov")]
+    fn wrap_many(&mut self, encrypt: bool, msgs: &[&[u8]]) -> Result<Vec<Bytes>, Error> {
+        unsafe { wrap_many(self.ctx, encrypt, msgs) }
     }
 
     #[cfg(feature = "iov")]
@@ -811,8 +2047,21 @@ impl SecurityContext for ClientCtx {
         unsafe { wrap_iov_length(self.ctx, encrypt, msg) }
     }
 
+    fn wrap_size_limit(
+        &mut self,
+        encrypt: bool,
+        qop: Qop,
+        output_size: u32,
+    ) -> Result<u32, Error> {
+        unsafe { wrap_size_limit(self.ctx, encrypt, qop, output_size) }
+    }
+
     fn unwrap(&mut self, msg: &[u8]) -> Result<Buf, Error> {
-        unsafe { unwrap(self.ctx, msg) }
+        unsafe { unwrap(self.ctx, msg, true).map(|(msg, _)| msg) }
+    }
+
+    fn unwrap_info(&mut self, msg: &[u8]) -> Result<(Buf, UnwrapInfo), Error> {
+        unsafe { unwrap(self.ctx, msg, false) }
     }
 
     #[cfg(feature = "iov")]
@@ -820,6 +2069,56 @@ impl SecurityContext for ClientCtx {
         unsafe { unwrap_iov(self.ctx, msg) }
     }
 
+    fn get_mic(&mut self, msg: &[u8]) -> Result<Buf, Error> {
+        unsafe { get_mic(self.ctx, Qop::DEFAULT, msg) }
+    }
+
+    fn get_mic_qop(&mut self, qop: Qop, msg: &[u8]) -> Result<Buf, Error> {
+        unsafe { get_mic(self.ctx, qop, msg) }
+    }
+
+    fn verify_mic(&mut self, msg: &[u8], token: &[u8]) -> Result<Qop, Error> {
+        unsafe { verify_mic(self.ctx, msg, token, true).map(|ifo| ifo.qop) }
+    }
+
+    fn verify_mic_info(&mut self, msg: &[u8], token: &[u8]) -> Result<MicInfo, Error> {
+        unsafe { verify_mic(self.ctx, msg, token, false) }
+    }
+
+    fn pseudo_random(
+        &mut self,
+        prf_key: PrfKey,
+        input: &[u8],
+        desired_output_len: usize,
+    ) -> Result<Buf, Error> {
+        unsafe { pseudo_random(self.ctx, prf_key, input, desired_output_len) }
+    }
+
+    fn set_option(&mut self, desired_object: &Oid, value: &[u8]) -> Result<bool, Error> {
+        unsafe { set_option(&mut self.ctx, desired_object, value) }
+    }
+
+    fn inquire_by_oid(&mut self, oid: &Oid) -> Result<BufferSet, Error> {
+        unsafe { inquire_by_oid(self.ctx, oid) }
+    }
+
+    #[cfg(any(feature = "mit", feature = "heimdal"))]
+    fn session_key(&mut self) -> Result<SessionKey, Error> {
+        unsafe { session_key(self.ctx) }
+    }
+
+    fn process_token(&mut self, token: &[u8]) -> Result<(), Error> {
+        unsafe { process_token(self.ctx, token) }
+    }
+
+    fn delete(mut self) -> Result<Option<Buf>, Error> {
+        unsafe { delete(&mut self.ctx) }
+    }
+
+    fn export(&mut self) -> Result<Buf, Error> {
+        unsafe { export(&mut self.ctx) }
+    }
+
     fn info(&mut self) -> Result<CtxInfo, Error> {
         unsafe { full_info(self.ctx) }
     }
@@ -832,10 +2131,14 @@ impl SecurityContext for ClientCtx {
         unsafe { target_name(self.ctx) }
     }
 
-    fn lifetime(&mut self) -> Result<Duration, Error> {
+    fn lifetime(&mut self) -> Result<Option<Duration>, Error> {
         unsafe { lifetime(self.ctx) }
     }
 
+    fn time_remaining(&mut self) -> Result<Option<Duration>, Error> {
+        unsafe { time_remaining(self.ctx) }
+    }
+
     fn mechanism(&mut self) -> Result<&'static Oid, Error> {
         unsafe { mechanism(self.ctx) }
     }
@@ -861,3 +2164,29 @@ impl SecurityContext for ClientCtx {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // only needs to compile
+    #[allow(dead_code)]
+    fn export_dyn(ctx: &mut dyn SecurityContext) -> Result<ExportedContext, Error> {
+        ExportedContext::new(ctx)
+    }
+
+    #[test]
+    fn backoff_saturates() {
+        assert_eq!(
+            next_backoff(Duration::from_millis(100)),
+            Duration::from_millis(200)
+        );
+        assert_eq!(next_backoff(Duration::from_secs(45)), MAX_RETRY_BACKOFF);
+        assert_eq!(next_backoff(Duration::MAX), MAX_RETRY_BACKOFF);
+        let mut backoff = Duration::from_millis(1);
+        for _ in 0..100 {
+            backoff = next_backoff(backoff);
+        }
+        assert_eq!(backoff, MAX_RETRY_BACKOFF);
+    }
+}
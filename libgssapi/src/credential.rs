@@ -1,19 +1,46 @@
-use crate::{error::{Error, MajorFlags, gss_error}, name::Name, oid::{OidSet, NO_OID_SET}};
+use crate::{
+    error::{gss_error, Error, ErrorDetail, MajorFlags},
+    name::Name,
+    oid::{indicate_mechs, Oid, OidSet, NO_OID, NO_OID_SET},
+    util::{time_from_c, time_to_c, zero, Buf, BufRef},
+};
 use libgssapi_sys::{
-    gss_OID_set, gss_acquire_cred, gss_cred_id_struct, gss_cred_id_t, gss_cred_usage_t,
-    gss_name_struct, gss_name_t, gss_release_cred, gss_inquire_cred, OM_uint32,
-    GSS_C_ACCEPT, GSS_C_BOTH, GSS_C_INITIATE, GSS_S_COMPLETE, _GSS_C_INDEFINITE,
+    gss_OID_set, gss_OID_set_desc, gss_acquire_cred, gss_acquire_cred_from,
+    gss_acquire_cred_impersonate_name, gss_acquire_cred_with_password, gss_add_cred,
+    gss_add_cred_impersonate_name, gss_const_key_value_set_t, gss_cred_id_struct,
+    gss_cred_id_t, gss_cred_usage_t, gss_export_cred, gss_import_cred, gss_inquire_cred,
+    gss_inquire_cred_by_mech, gss_key_value_element_desc, gss_key_value_set_desc,
+    gss_name_struct, gss_name_t, gss_release_cred, gss_set_cred_option, gss_store_cred,
+    gss_store_cred_into, OM_uint32, GSS_C_ACCEPT, GSS_C_BOTH, GSS_C_INITIATE,
+    GSS_S_COMPLETE,
 };
-use std::{ptr, fmt, time::Duration};
+use std::{convert::TryFrom, ffi::CString, fmt, ptr, sync::Arc, time::Duration};
 
 #[derive(Debug)]
 pub struct CredInfo {
     pub name: Name,
-    pub lifetime: Duration,
+    /// `None` if the credential lifetime is indefinite
+    pub lifetime: Option<Duration>,
+    /// what the credential can really be used for, see
+    /// `Cred::split_usage`
     pub usage: CredUsage,
     pub mechanisms: OidSet,
 }
 
+/// Information about one mechanism's element of a credential, see
+/// `Cred::info_by_mech`
+#[derive(Debug)]
+pub struct CredMechInfo {
+    pub name: Name,
+    /// `None` if the lifetime is indefinite, zero if the element
+    /// can't be used to initiate
+    pub initiator_lifetime: Option<Duration>,
+    /// `None` if the lifetime is indefinite, zero if the element
+    /// can't be used to accept
+    pub acceptor_lifetime: Option<Duration>,
+    pub usage: CredUsage,
+}
+
 struct CredInfoC {
     name: Option<gss_name_t>,
     lifetime: Option<u32>,
@@ -32,7 +59,7 @@ impl CredInfoC {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CredUsage {
     Accept,
     Initiate,
@@ -45,7 +72,14 @@ impl CredUsage {
             GSS_C_BOTH => Ok(CredUsage::Both),
             GSS_C_INITIATE => Ok(CredUsage::Initiate),
             GSS_C_ACCEPT => Ok(CredUsage::Accept),
-            _ => return Err(Error {major: MajorFlags::GSS_S_FAILURE, minor: 0})
+            _ => {
+                return Err(Error {
+                    major: MajorFlags::GSS_S_FAILURE,
+                    minor: 0,
+                    detail: Some(ErrorDetail::UnknownCredUsage(c)),
+                    mech: None,
+                })
+            }
         }
     }
 
@@ -58,10 +92,138 @@ impl CredUsage {
     }
 }
 
-/// gssapi credentials.
-pub struct Cred(gss_cred_id_t);
+impl TryFrom<i32> for CredUsage {
+    type Error = Error;
+
+    fn try_from(c: i32) -> Result<Self, Error> {
+        CredUsage::from_c(c)
+    }
+}
+
+impl From<CredUsage> for i32 {
+    fn from(usage: CredUsage) -> i32 {
+        usage.to_c() as i32
+    }
+}
+
+/// What a credential store holds for one mechanism, see
+/// `CredStore::inspect`
+#[derive(Debug)]
+pub struct CredStoreEntry {
+    // owns the mech oid, which may be one we don't know
+    mechs: OidSet,
+    /// the acquired credential's info, or why it couldn't be acquired
+    pub info: Result<CredInfo, Error>,
+}
+
+impl CredStoreEntry {
+    /// The mechanism this entry is for
+    pub fn mech(&self) -> &Oid {
+        &self.mechs[0]
+    }
+}
+
+/// A set of key value pairs describing a credential store, this
+/// lets you use a specific ccache or keytab without changing the
+/// process environment. The valid keys are mechanism specific, for
+/// MIT Kerberos they include `ccache`, `client_keytab`, `keytab`,
+/// and `rcache`. e.g.
+///
+/// `CredStore::new().set("ccache", "FILE:/tmp/tenant1")`
+#[derive(Debug, Clone, Default)]
+pub struct CredStore(Vec<(CString, CString)>);
+
+impl CredStore {
+    /// Create an empty credential store
+    pub fn new() -> CredStore {
+        CredStore(Vec::new())
+    }
+
+    /// Set `key` to `value`, replacing any previous value of
+    /// `key`. Panics if either `key` or `value` contains a nul byte.
+    pub fn set(mut self, key: &str, value: &str) -> CredStore {
+        let key = CString::new(key).expect("cred store key contains a nul byte");
+        let value = CString::new(value).expect("cred store value contains a nul byte");
+        match self.0.iter_mut().find(|(k, _)| k == &key) {
+            Some((_, v)) => *v = value,
+            None => self.0.push((key, value)),
+        }
+        self
+    }
+
+    /// Report what this store holds for `usage`, for diagnosing
+    /// keytab and ccache problems. gssapi can't enumerate a store, so
+    /// this tries to acquire the default credential for each mechanism
+    /// the implementation supports, reporting either the credential's
+    /// info (name, lifetime, etc) or the reason acquisition failed.
+    pub fn inspect(&self, usage: CredUsage) -> Result<Vec<CredStoreEntry>, Error> {
+        let mut report = Vec::new();
+        for mech in &indicate_mechs()? {
+            let mut mechs = OidSet::new()?;
+            mechs.add(mech)?;
+            let info = Cred::acquire_from(None, None, usage, Some(&mechs), self)
+                .and_then(|cred| cred.info());
+            report.push(CredStoreEntry { mechs, info });
+        }
+        Ok(report)
+    }
+
+    /// Call `f` with a C representation of the store, which is only
+    /// valid for the duration of the call.
+    fn with_c<R>(&self, f: impl FnOnce(gss_const_key_value_set_t) -> R) -> R {
+        let mut elements = self
+            .0
+            .iter()
+            .map(|(key, value)| gss_key_value_element_desc {
+                key: key.as_ptr(),
+                value: value.as_ptr(),
+            })
+            .collect::<Vec<_>>();
+        let set = gss_key_value_set_desc {
+            count: elements.len() as OM_uint32,
+            elements: elements.as_mut_ptr(),
+        };
+        f(&set as gss_const_key_value_set_t)
+    }
+}
+
+// the name and lifetime a credential was originally asked for, so
+// it can be acquired again with exactly the same request rather than
+// whatever name and lifetime the first acquisition was granted
+struct CredRequest {
+    name: Option<Name>,
+    time_req: Option<Duration>,
+}
 
-impl Drop for Cred {
+impl CredRequest {
+    fn new(
+        name: Option<&Name>,
+        time_req: Option<Duration>,
+    ) -> Result<Arc<CredRequest>, Error> {
+        let name = match name {
+            None => None,
+            Some(name) => Some(name.duplicate()?),
+        };
+        Ok(Arc::new(CredRequest { name, time_req }))
+    }
+}
+
+// where a credential came from, so `refresh` and `with_usage` can
+// acquire it again from the same place
+#[derive(Clone)]
+enum CredSource {
+    // `acquire`, from the default store
+    Default(Arc<CredRequest>),
+    // `acquire_from` this store
+    Store(Arc<CredRequest>, CredStore),
+    // delegated, imported, or acquired some other way that can't
+    // simply be repeated
+    Other,
+}
+
+struct CredInner(gss_cred_id_t, CredSource);
+
+impl Drop for CredInner {
     fn drop(&mut self) {
         if !self.0.is_null() {
             let mut minor = GSS_S_COMPLETE;
@@ -75,8 +237,14 @@ impl Drop for Cred {
     }
 }
 
-unsafe impl Send for Cred {}
-unsafe impl Sync for Cred {}
+/// A gssapi credential. Cloning a `Cred` is cheap, the clones share
+/// the same underlying gssapi handle, which is released when the last
+/// clone is dropped.
+#[derive(Clone)]
+pub struct Cred(Arc<CredInner>);
+
+unsafe impl Send for CredInner {}
+unsafe impl Sync for CredInner {}
 
 impl fmt::Debug for Cred {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
@@ -87,20 +255,62 @@ impl fmt::Debug for Cred {
     }
 }
 
+/// If only one mechanism was asked for then any error must have come
+/// from it. The oid points into `mechs`, which the error outlives, so
+/// only return it if we have a static copy.
+fn single_mech(mechs: Option<&OidSet>) -> Option<Oid> {
+    match mechs {
+        Some(mechs) if mechs.len() == 1 => mechs[0].known(),
+        _ => None,
+    }
+}
+
+// a credential acquired for `CredUsage::Both` must really be usable
+// both ways, rather than silently handing back half of one
+fn check_both(cred: Cred, usage: CredUsage) -> Result<Cred, Error> {
+    if usage == CredUsage::Both && cred.split_usage()? != Some(CredUsage::Both) {
+        Err(Error {
+            major: MajorFlags::GSS_S_NO_CRED,
+            minor: 0,
+            detail: Some(ErrorDetail::UsageUnavailable(CredUsage::Both)),
+            mech: None,
+        })
+    } else {
+        Ok(cred)
+    }
+}
+
+// fold `l` into the shortest lifetime seen so far, `None` meaning
+// indefinite
+fn min_lifetime(acc: &mut Option<Option<Duration>>, l: Option<Duration>) {
+    *acc = match (*acc, l) {
+        (None, l) | (Some(None), l) => Some(l),
+        (Some(Some(acc)), None) => Some(Some(acc)),
+        (Some(Some(acc)), Some(l)) => Some(Some(acc.min(l))),
+    }
+}
+
 impl Cred {
     /// Acquire gssapi credentials for `name` or the default name,
     /// lasting for `time_req` or as long as possible, for the purpose
     /// of `usage`, and for use with `desired_mechs` or the default
     /// mechanism.
+    ///
+    /// Mechanisms may grant less than `CredUsage::Both`, e.g. a
+    /// keytab alone may only yield an acceptor credential. Rather
+    /// than return a credential that only works one way, asking for
+    /// `Both` fails with `GSS_S_NO_CRED` and
+    /// `ErrorDetail::UsageUnavailable` in that case. Acquire each
+    /// usage separately if either will do.
     pub fn acquire(
         name: Option<&Name>,
         time_req: Option<Duration>,
         usage: CredUsage,
         desired_mechs: Option<&OidSet>,
     ) -> Result<Cred, Error> {
-        let time_req = time_req.map(|d| d.as_secs() as u32).unwrap_or(_GSS_C_INDEFINITE);
+        let request = CredRequest::new(name, time_req)?;
+        let time_req = time_to_c(time_req);
         let mut minor = GSS_S_COMPLETE;
-        let usage = usage.to_c();
         let mut cred = ptr::null_mut::<gss_cred_id_struct>();
         let major = unsafe {
             gss_acquire_cred(
@@ -114,6 +324,168 @@ impl Cred {
                     None => NO_OID_SET,
                     Some(desired_mechs) => desired_mechs.to_c()
                 },
+                usage.to_c() as gss_cred_usage_t,
+                &mut cred as *mut gss_cred_id_t,
+                ptr::null_mut::<gss_OID_set>(),
+                ptr::null_mut::<OM_uint32>(),
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            let cred = Cred(Arc::new(CredInner(cred, CredSource::Default(request))));
+            check_both(cred, usage)
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: single_mech(desired_mechs),
+            })
+        }
+    }
+
+    /// The same as `acquire`, except the credential is acquired from
+    /// the specified credential `store` instead of the default
+    /// one, and `CredUsage::Both` is checked in the same way. e.g.
+    /// for an acceptor,
+    ///
+    /// `CredStore::new().set("keytab", "/etc/krb5.keytab")`
+    ///
+    /// or `ccache` and/or `client_keytab` for an initiator. The
+    /// credential remembers its store, so a context initiated with it
+    /// uses that ccache rather than `KRB5CCNAME`, and threads using
+    /// different stores don't interfere with each other. e.g.
+    ///
+    /// ```no_run
+    /// # use libgssapi::{credential::*, context::ClientCtx, name::Name};
+    /// let store = CredStore::new().set("ccache", "MEMORY:worker7");
+    /// let cred = Cred::acquire_from(None, None, CredUsage::Initiate, None, &store)?;
+    /// let target = Name::hostbased_service("nfs", "server")?;
+    /// let (ctx, token) = ClientCtx::builder(target).cred(cred).build()?;
+    /// # Ok::<(), libgssapi::error::Error>(())
+    /// ```
+    pub fn acquire_from(
+        name: Option<&Name>,
+        time_req: Option<Duration>,
+        usage: CredUsage,
+        desired_mechs: Option<&OidSet>,
+        store: &CredStore,
+    ) -> Result<Cred, Error> {
+        let request = CredRequest::new(name, time_req)?;
+        let time_req = time_to_c(time_req);
+        let mut minor = GSS_S_COMPLETE;
+        let mut cred = ptr::null_mut::<gss_cred_id_struct>();
+        let major = store.with_c(|store| unsafe {
+            gss_acquire_cred_from(
+                &mut minor as *mut OM_uint32,
+                match name {
+                    None => ptr::null_mut::<gss_name_struct>(),
+                    Some(n) => n.to_c(),
+                },
+                time_req,
+                match desired_mechs {
+                    None => NO_OID_SET,
+                    Some(desired_mechs) => desired_mechs.to_c(),
+                },
+                usage.to_c() as gss_cred_usage_t,
+                store,
+                &mut cred as *mut gss_cred_id_t,
+                ptr::null_mut::<gss_OID_set>(),
+                ptr::null_mut::<OM_uint32>(),
+            )
+        });
+        if major == GSS_S_COMPLETE {
+            let source = CredSource::Store(request, store.clone());
+            let cred = Cred(Arc::new(CredInner(cred, source)));
+            check_both(cred, usage)
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: single_mech(desired_mechs),
+            })
+        }
+    }
+
+    /// Acquire gssapi credentials for `name` by authenticating with
+    /// `password`, instead of using an existing credential cache or
+    /// keytab. The other arguments are the same as `acquire`,
+    /// including the check that `CredUsage::Both` is really granted.
+    ///
+    /// The password is passed to gssapi directly from the slice you
+    /// provide, it is never copied by this library, and the slice is
+    /// zeroed before this returns, whether or not it succeeded, so the
+    /// password isn't left lingering in your buffer either. Any other
+    /// copies you made are up to you.
+    pub fn acquire_with_password(
+        name: &Name,
+        password: &mut [u8],
+        time_req: Option<Duration>,
+        usage: CredUsage,
+        desired_mechs: Option<&OidSet>,
+    ) -> Result<Cred, Error> {
+        let time_req = time_to_c(time_req);
+        let mut minor = GSS_S_COMPLETE;
+        let mut password_buf = BufRef::from(&*password);
+        let mut cred = ptr::null_mut::<gss_cred_id_struct>();
+        let major = unsafe {
+            gss_acquire_cred_with_password(
+                &mut minor as *mut OM_uint32,
+                name.to_c(),
+                password_buf.to_c(),
+                time_req,
+                match desired_mechs {
+                    None => NO_OID_SET,
+                    Some(desired_mechs) => desired_mechs.to_c(),
+                },
+                usage.to_c() as gss_cred_usage_t,
+                &mut cred as *mut gss_cred_id_t,
+                ptr::null_mut::<gss_OID_set>(),
+                ptr::null_mut::<OM_uint32>(),
+            )
+        };
+        zero(password);
+        if major == GSS_S_COMPLETE {
+            check_both(Cred(Arc::new(CredInner(cred, CredSource::Other))), usage)
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: single_mech(desired_mechs),
+            })
+        }
+    }
+
+    /// Acquire credentials for `name` using the `impersonator`
+    /// credential, which must belong to a service, without knowing
+    /// any secret belonging to `name` (Kerberos S4U2Self, also known
+    /// as protocol transition). The result can be used as the
+    /// initiator credential of a `ClientCtx` to talk to a backend as
+    /// `name`. If the KDC doesn't allow the service to impersonate
+    /// users this usually fails with `GSS_S_UNAVAILABLE` or
+    /// `GSS_S_FAILURE`.
+    pub fn acquire_impersonate(
+        impersonator: &Cred,
+        name: &Name,
+        time_req: Option<Duration>,
+        usage: CredUsage,
+        desired_mechs: Option<&OidSet>,
+    ) -> Result<Cred, Error> {
+        let time_req = time_to_c(time_req);
+        let mut minor = GSS_S_COMPLETE;
+        let usage = usage.to_c();
+        let mut cred = ptr::null_mut::<gss_cred_id_struct>();
+        let major = unsafe {
+            gss_acquire_cred_impersonate_name(
+                &mut minor as *mut OM_uint32,
+                impersonator.to_c(),
+                name.to_c(),
+                time_req,
+                match desired_mechs {
+                    None => NO_OID_SET,
+                    Some(desired_mechs) => desired_mechs.to_c(),
+                },
                 usage as gss_cred_usage_t,
                 &mut cred as *mut gss_cred_id_t,
                 ptr::null_mut::<gss_OID_set>(),
@@ -121,28 +493,392 @@ impl Cred {
             )
         };
         if major == GSS_S_COMPLETE {
-            Ok(Cred(cred))
+            Ok(Cred(Arc::new(CredInner(cred, CredSource::Other))))
         } else {
             Err(Error {
                 major: unsafe { MajorFlags::from_bits_unchecked(major) },
-                minor
+                minor,
+                detail: None,
+                mech: single_mech(desired_mechs),
+            })
+        }
+    }
+
+    /// Add a credential element for `desired_mech` to this
+    /// credential, for `name` or the default name, and for the
+    /// purpose of `usage`. This credential is not modified, instead a
+    /// new credential containing the union of this credential and the
+    /// new element is returned, along with the initiator and acceptor
+    /// lifetimes actually granted by the mechanism (in that order,
+    /// `None` meaning indefinite).
+    pub fn add(
+        &self,
+        name: Option<&Name>,
+        desired_mech: &Oid,
+        usage: CredUsage,
+        initiator_time_req: Option<Duration>,
+        acceptor_time_req: Option<Duration>,
+    ) -> Result<(Cred, Option<Duration>, Option<Duration>), Error> {
+        let initiator_time_req = time_to_c(initiator_time_req);
+        let acceptor_time_req = time_to_c(acceptor_time_req);
+        let mut minor = GSS_S_COMPLETE;
+        let usage = usage.to_c();
+        let mut cred = ptr::null_mut::<gss_cred_id_struct>();
+        let mut initiator_time_rec: OM_uint32 = 0;
+        let mut acceptor_time_rec: OM_uint32 = 0;
+        let major = unsafe {
+            gss_add_cred(
+                &mut minor as *mut OM_uint32,
+                self.to_c(),
+                match name {
+                    None => ptr::null_mut::<gss_name_struct>(),
+                    Some(n) => n.to_c(),
+                },
+                desired_mech.to_c(),
+                usage as gss_cred_usage_t,
+                initiator_time_req,
+                acceptor_time_req,
+                &mut cred as *mut gss_cred_id_t,
+                ptr::null_mut::<gss_OID_set>(),
+                &mut initiator_time_rec as *mut OM_uint32,
+                &mut acceptor_time_rec as *mut OM_uint32,
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            Ok((
+                Cred(Arc::new(CredInner(cred, CredSource::Other))),
+                time_from_c(initiator_time_rec),
+                time_from_c(acceptor_time_rec),
+            ))
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: desired_mech.known(),
+            })
+        }
+    }
+
+    /// The same as `add`, except the new element is acquired for
+    /// `name` using the `impersonator` credential (Kerberos
+    /// S4U2Proxy, also known as constrained delegation). For this to
+    /// work the KDC must allow the impersonator's service to delegate
+    /// to the target service, and the ticket being used must be
+    /// forwardable (e.g. it came from `acquire_impersonate`, or it
+    /// was delegated by a client and the service is marked
+    /// OK-AS-DELEGATE). Otherwise the KDC will refuse, usually
+    /// reported as `GSS_S_FAILURE`.
+    pub fn add_impersonate(
+        &self,
+        impersonator: &Cred,
+        name: &Name,
+        desired_mech: &Oid,
+        usage: CredUsage,
+        initiator_time_req: Option<Duration>,
+        acceptor_time_req: Option<Duration>,
+    ) -> Result<(Cred, Option<Duration>, Option<Duration>), Error> {
+        let initiator_time_req = time_to_c(initiator_time_req);
+        let acceptor_time_req = time_to_c(acceptor_time_req);
+        let mut minor = GSS_S_COMPLETE;
+        let usage = usage.to_c();
+        let mut cred = ptr::null_mut::<gss_cred_id_struct>();
+        let mut initiator_time_rec: OM_uint32 = 0;
+        let mut acceptor_time_rec: OM_uint32 = 0;
+        let major = unsafe {
+            gss_add_cred_impersonate_name(
+                &mut minor as *mut OM_uint32,
+                self.to_c(),
+                impersonator.to_c(),
+                name.to_c(),
+                desired_mech.to_c(),
+                usage as gss_cred_usage_t,
+                initiator_time_req,
+                acceptor_time_req,
+                &mut cred as *mut gss_cred_id_t,
+                ptr::null_mut::<gss_OID_set>(),
+                &mut initiator_time_rec as *mut OM_uint32,
+                &mut acceptor_time_rec as *mut OM_uint32,
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            Ok((
+                Cred(Arc::new(CredInner(cred, CredSource::Other))),
+                time_from_c(initiator_time_rec),
+                time_from_c(acceptor_time_rec),
+            ))
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: desired_mech.known(),
+            })
+        }
+    }
+
+    /// Store this credential in the default credential store (e.g. the
+    /// kerberos ccache) for the purpose of `usage`, for
+    /// `desired_mech` or all the mechanisms of the credential. If
+    /// `overwrite` is false and the store already contains a
+    /// credential then an error will be returned. If `default_cred`
+    /// is true then the stored credential will become the default
+    /// credential. Returns the set of mechanisms that were actually
+    /// stored, and the usage they were stored with.
+    pub fn store(
+        &self,
+        usage: CredUsage,
+        desired_mech: Option<&Oid>,
+        overwrite: bool,
+        default_cred: bool,
+    ) -> Result<(OidSet, CredUsage), Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut elements_stored = ptr::null_mut::<gss_OID_set_desc>();
+        let mut usage_stored: gss_cred_usage_t = 0;
+        let major = unsafe {
+            gss_store_cred(
+                &mut minor as *mut OM_uint32,
+                self.to_c(),
+                usage.to_c() as gss_cred_usage_t,
+                match desired_mech {
+                    None => NO_OID,
+                    Some(mech) => mech.to_c(),
+                },
+                if overwrite { 1 } else { 0 },
+                if default_cred { 1 } else { 0 },
+                &mut elements_stored as *mut gss_OID_set,
+                &mut usage_stored as *mut gss_cred_usage_t,
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            let elements_stored = unsafe { OidSet::from_c(elements_stored) };
+            Ok((elements_stored, CredUsage::from_c(usage_stored)?))
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: desired_mech.and_then(Oid::known),
+            })
+        }
+    }
+
+    /// The same as `store`, except the credential is stored into the
+    /// specified credential `store` instead of the default one.
+    pub fn store_into(
+        &self,
+        usage: CredUsage,
+        desired_mech: Option<&Oid>,
+        overwrite: bool,
+        default_cred: bool,
+        store: &CredStore,
+    ) -> Result<(OidSet, CredUsage), Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut elements_stored = ptr::null_mut::<gss_OID_set_desc>();
+        let mut usage_stored: gss_cred_usage_t = 0;
+        let major = store.with_c(|store| unsafe {
+            gss_store_cred_into(
+                &mut minor as *mut OM_uint32,
+                self.to_c(),
+                usage.to_c() as gss_cred_usage_t,
+                match desired_mech {
+                    None => NO_OID,
+                    Some(mech) => mech.to_c(),
+                },
+                if overwrite { 1 } else { 0 },
+                if default_cred { 1 } else { 0 },
+                store,
+                &mut elements_stored as *mut gss_OID_set,
+                &mut usage_stored as *mut gss_cred_usage_t,
+            )
+        });
+        if major == GSS_S_COMPLETE {
+            let elements_stored = unsafe { OidSet::from_c(elements_stored) };
+            Ok((elements_stored, CredUsage::from_c(usage_stored)?))
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: desired_mech.and_then(Oid::known),
+            })
+        }
+    }
+
+    // acquire this credential again with the same requested name
+    // and lifetime and the same mechanisms, from the place it
+    // originally came from, for `usage` or the same usage
+    fn reacquire(&self, usage: Option<CredUsage>) -> Result<Cred, Error> {
+        let (request, store) = match &self.0 .1 {
+            CredSource::Default(request) => (request, None),
+            CredSource::Store(request, store) => (request, Some(store)),
+            CredSource::Other => {
+                return Err(Error {
+                    major: MajorFlags::GSS_S_UNAVAILABLE,
+                    minor: 0,
+                    detail: Some(ErrorDetail::NotReacquirable),
+                    mech: None,
+                })
+            }
+        };
+        let info = self.info()?;
+        let name = request.name.as_ref();
+        let time_req = request.time_req;
+        let usage = usage.unwrap_or(info.usage);
+        let mechs = Some(&info.mechanisms);
+        match store {
+            None => Cred::acquire(name, time_req, usage, mechs),
+            Some(store) => Cred::acquire_from(name, time_req, usage, mechs, store),
+        }
+    }
+
+    /// Re-acquire this credential with the same name, lifetime, usage
+    /// and mechanisms, from the same place it was acquired from, e.g. to
+    /// pick up a fresh ticket from the keytab before the current one
+    /// expires. On success this credential refers to the new handle,
+    /// clones made before the refresh keep the old one. On failure
+    /// this credential is left unchanged.
+    ///
+    /// Only credentials from `acquire` or `acquire_from` (and
+    /// `set_option` on them) can be refreshed. Others, e.g.
+    /// delegated or imported credentials, or ones from
+    /// `acquire_with_password`, `acquire_impersonate`, `add` or
+    /// `add_impersonate`, can't be acquired again the same way, and
+    /// fail with `GSS_S_UNAVAILABLE` and
+    /// `ErrorDetail::NotReacquirable`.
+    pub fn refresh(&mut self) -> Result<(), Error> {
+        *self = self.reacquire(None)?;
+        Ok(())
+    }
+
+    /// Get a new credential with the same name and mechanisms as
+    /// this one, restricted to `usage`, e.g. an initiator only handle
+    /// from a `CredUsage::Both` credential so it can't accidentally
+    /// be used to accept. The credential is re-acquired from the
+    /// same place as this one, so this fails in the same way as
+    /// `refresh` for credentials that can't be refreshed. If this
+    /// credential can't be used for `usage`, or the result isn't
+    /// restricted to it, this fails with `GSS_S_NO_CRED` and
+    /// `ErrorDetail::UsageUnavailable`.
+    pub fn with_usage(&self, usage: CredUsage) -> Result<Cred, Error> {
+        let unavailable = Error {
+            major: MajorFlags::GSS_S_NO_CRED,
+            minor: 0,
+            detail: Some(ErrorDetail::UsageUnavailable(usage)),
+            mech: None,
+        };
+        match (self.split_usage()?, usage) {
+            (Some(CredUsage::Both), _) => (),
+            (Some(available), usage) if available == usage => (),
+            _ => return Err(unavailable),
+        }
+        let cred = self.reacquire(Some(usage))?;
+        if cred.split_usage()? == Some(usage) {
+            Ok(cred)
+        } else {
+            Err(unavailable)
+        }
+    }
+
+    /// Set the mechanism specific option `desired_object` on this
+    /// credential, e.g. `GSS_KRB5_CRED_NO_CI_FLAGS_X`. The meaning of
+    /// `value` depends on the option. The mechanism may replace the
+    /// underlying credential handle, in which case this credential
+    /// is updated to refer to the new one. Options set on a handle
+    /// that is shared with clones of this credential affect all of
+    /// them.
+    pub fn set_option(
+        &mut self,
+        desired_object: &Oid,
+        value: &[u8],
+    ) -> Result<(), Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut value = BufRef::from(value);
+        let mut cred = unsafe { self.to_c() };
+        let major = unsafe {
+            gss_set_cred_option(
+                &mut minor as *mut OM_uint32,
+                &mut cred as *mut gss_cred_id_t,
+                desired_object.to_c(),
+                value.to_c(),
+            )
+        };
+        if cred != unsafe { self.to_c() } {
+            match Arc::get_mut(&mut self.0) {
+                Some(inner) => inner.0 = cred,
+                None => self.0 = Arc::new(CredInner(cred, self.0 .1.clone())),
+            }
+        }
+        if major == GSS_S_COMPLETE {
+            Ok(())
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: None,
+            })
+        }
+    }
+
+    /// Serialize this credential into a token that can be passed to
+    /// another process and turned back into a credential with
+    /// `import`.
+    pub fn export(&self) -> Result<Buf, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut token = Buf::empty();
+        let major = unsafe {
+            gss_export_cred(&mut minor as *mut OM_uint32, self.to_c(), token.to_c())
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(token)
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: None,
+            })
+        }
+    }
+
+    /// Import a credential from a token produced by `export`.
+    pub fn import(token: &[u8]) -> Result<Cred, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut token = BufRef::from(token);
+        let mut cred = ptr::null_mut::<gss_cred_id_struct>();
+        let major = unsafe {
+            gss_import_cred(
+                &mut minor as *mut OM_uint32,
+                token.to_c(),
+                &mut cred as *mut gss_cred_id_t,
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(Cred(Arc::new(CredInner(cred, CredSource::Other))))
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: None,
             })
         }
     }
 
     pub(crate) unsafe fn from_c(cred: gss_cred_id_t) -> Cred {
-        Cred(cred)
+        Cred(Arc::new(CredInner(cred, CredSource::Other)))
     }
 
     pub(crate) unsafe fn to_c(&self) -> gss_cred_id_t {
-        self.0
+        self.0 .0
     }
 
     unsafe fn info_c(&self, mut ifo: CredInfoC) -> Result<CredInfoC, Error> {
         let mut minor: u32 = 0;
         let major = gss_inquire_cred(
             &mut minor as *mut OM_uint32,
-            self.0,
+            self.to_c(),
             match ifo.name {
                 None => ptr::null_mut::<gss_name_t>(),
                 Some(ref mut n) => n as *mut gss_name_t
@@ -168,30 +904,132 @@ impl Cred {
             if let Some(s) = ifo.mechanisms {
                 OidSet::from_c(s);
             }
-            Err(Error { major: MajorFlags::from_bits_unchecked(major), minor })
+            Err(Error {
+                major: MajorFlags::from_bits_unchecked(major),
+                minor,
+                detail: None,
+                mech: None,
+            })
         } else {
             Ok(ifo)
         }
     }
 
-    /// Return all the information associated with this credential
+    /// Return all the information associated with this credential.
+    /// The usage is the one the credential can really be used for,
+    /// see `split_usage`. Finding that out costs a
+    /// `gss_inquire_cred_by_mech` call per mechanism on top of the
+    /// `gss_inquire_cred` call, use `usage` or `status` for the
+    /// cheaper nominal usage.
     pub fn info(&self) -> Result<CredInfo, Error> {
-        unsafe {
+        let (name, lifetime, usage, mechanisms) = unsafe {
             let c = self.info_c(CredInfoC {
                 name: Some(ptr::null_mut()),
                 lifetime: Some(0),
                 usage: Some(0),
                 mechanisms: Some(ptr::null_mut()),
             })?;
-            Ok(CredInfo {
-                name: Name::from_c(c.name.unwrap()),
-                lifetime: Duration::from_secs(c.lifetime.unwrap() as u64),
-                usage: CredUsage::from_c(c.usage.unwrap())?,
-                mechanisms: OidSet::from_c(c.mechanisms.unwrap())
+            (
+                Name::from_c(c.name.unwrap()),
+                time_from_c(c.lifetime.unwrap()),
+                c.usage.unwrap(),
+                OidSet::from_c(c.mechanisms.unwrap()),
+            )
+        };
+        Ok(CredInfo {
+            name,
+            lifetime,
+            usage: self.effective_usage(CredUsage::from_c(usage)?)?,
+            mechanisms,
+        })
+    }
+
+    /// Return the information associated with the element of this
+    /// credential for `mech`. Unlike `info`, which reports the
+    /// shortest lifetime of any element, this reports the initiator
+    /// and acceptor lifetimes separately.
+    pub fn info_by_mech(&self, mech: &Oid) -> Result<CredMechInfo, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut name = ptr::null_mut::<gss_name_struct>();
+        let mut initiator_lifetime: OM_uint32 = 0;
+        let mut acceptor_lifetime: OM_uint32 = 0;
+        let mut usage: gss_cred_usage_t = 0;
+        let major = unsafe {
+            gss_inquire_cred_by_mech(
+                &mut minor as *mut OM_uint32,
+                self.to_c(),
+                mech.to_c(),
+                &mut name as *mut gss_name_t,
+                &mut initiator_lifetime as *mut OM_uint32,
+                &mut acceptor_lifetime as *mut OM_uint32,
+                &mut usage as *mut gss_cred_usage_t,
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            let name = unsafe { Name::from_c(name) };
+            Ok(CredMechInfo {
+                name,
+                initiator_lifetime: time_from_c(initiator_lifetime),
+                acceptor_lifetime: time_from_c(acceptor_lifetime),
+                usage: CredUsage::from_c(usage)?,
+            })
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: mech.known(),
             })
         }
     }
 
+    /// Return the remaining initiator and acceptor lifetimes of this
+    /// credential, the shortest of each over all the mechanism
+    /// elements that can be used that way, `None` if it is
+    /// indefinite. If no element can be used for one of the roles
+    /// then its lifetime is zero.
+    pub fn lifetimes(&self) -> Result<(Option<Duration>, Option<Duration>), Error> {
+        let mut initiator = None;
+        let mut acceptor = None;
+        for mech in &self.mechanisms()? {
+            let ifo = self.info_by_mech(mech)?;
+            if let CredUsage::Initiate | CredUsage::Both = ifo.usage {
+                min_lifetime(&mut initiator, ifo.initiator_lifetime);
+            }
+            if let CredUsage::Accept | CredUsage::Both = ifo.usage {
+                min_lifetime(&mut acceptor, ifo.acceptor_lifetime);
+            }
+        }
+        let zero = Some(Duration::from_secs(0));
+        Ok((initiator.unwrap_or(zero), acceptor.unwrap_or(zero)))
+    }
+
+    /// Return the usage this credential can really be used for, or
+    /// `None` if it can't be used at all (e.g. it has expired). This
+    /// can be less than what was asked for, e.g. acquiring with
+    /// `CredUsage::Both` from a keytab with no client keytab or ccache
+    /// may only yield an acceptor credential (`acquire` refuses to
+    /// return such a credential, but others, e.g. delegated ones,
+    /// can be one sided too). This is based on the per mechanism
+    /// lifetimes, see `lifetimes`, rather than the usage reported by
+    /// the mechanism for the whole credential.
+    pub fn split_usage(&self) -> Result<Option<CredUsage>, Error> {
+        let zero = Some(Duration::from_secs(0));
+        let (initiator, acceptor) = self.lifetimes()?;
+        Ok(match (initiator != zero, acceptor != zero) {
+            (true, true) => Some(CredUsage::Both),
+            (true, false) => Some(CredUsage::Initiate),
+            (false, true) => Some(CredUsage::Accept),
+            (false, false) => None,
+        })
+    }
+
+    // `split_usage`, or the nominal `usage` if the credential can't
+    // be used at all, e.g. it has expired
+    fn effective_usage(&self, usage: CredUsage) -> Result<CredUsage, Error> {
+        Ok(self.split_usage()?.unwrap_or(usage))
+    }
+
     /// Return the name associated with this credential
     pub fn name(&self) -> Result<Name, Error> {
         unsafe {
@@ -203,25 +1041,50 @@ impl Cred {
         }
     }
 
-    /// Return the lifetime of this credential
-    pub fn lifetime(&self) -> Result<Duration, Error> {
+    /// Return the lifetime of this credential, `None` if it is
+    /// indefinite
+    pub fn lifetime(&self) -> Result<Option<Duration>, Error> {
         unsafe {
             let c = self.info_c(CredInfoC {
                 lifetime: Some(0),
                 .. CredInfoC::empty()
             })?;
-            Ok(Duration::from_secs(c.lifetime.unwrap() as u64))
+            Ok(time_from_c(c.lifetime.unwrap()))
         }
     }
 
-    /// Return the allowed usage of this credential
+    /// Return the usage the mechanism reports for this credential,
+    /// in one `gss_inquire_cred` call. This may be more than the
+    /// credential can really be used for, e.g. `Both` for a
+    /// credential that can only accept, use `split_usage` (or `info`)
+    /// to find out what really works.
     pub fn usage(&self) -> Result<CredUsage, Error> {
         unsafe {
             let c = self.info_c(CredInfoC {
                 usage: Some(0),
-                .. CredInfoC::empty()
+                ..CredInfoC::empty()
+            })?;
+            CredUsage::from_c(c.usage.unwrap())
+        }
+    }
+
+    /// Return the lifetime and the allowed usage of this credential
+    /// in one call. Unlike `info` this doesn't allocate the name and
+    /// the mechanism set, which makes it cheap enough to poll. For
+    /// the same reason the usage is the one the mechanism reports, as
+    /// with `usage`, which may be more than the credential can really
+    /// be used for, see `split_usage`.
+    pub fn status(&self) -> Result<(Option<Duration>, CredUsage), Error> {
+        unsafe {
+            let c = self.info_c(CredInfoC {
+                lifetime: Some(0),
+                usage: Some(0),
+                ..CredInfoC::empty()
             })?;
-            Ok(CredUsage::from_c(c.usage.unwrap())?)
+            Ok((
+                time_from_c(c.lifetime.unwrap()),
+                CredUsage::from_c(c.usage.unwrap())?,
+            ))
         }
     }
 
@@ -235,4 +1098,20 @@ impl Cred {
             Ok(OidSet::from_c(c.mechanisms.unwrap()))
         }
     }
+
+    /// Return true if this credential belongs to `name`. `name` need
+    /// not be canonical, e.g. `Name::user("alice")` matches a krb5
+    /// credential for `alice@EXAMPLE.COM`.
+    pub fn is_for_name(&self, name: &Name) -> Result<bool, Error> {
+        self.name()?.compare(name)
+    }
+
+    /// Return true if this credential belongs to the same name as the
+    /// default credential for its usage, e.g. the principal in the
+    /// default ccache for an initiator. This fails if there is no
+    /// default credential.
+    pub fn is_default(&self) -> Result<bool, Error> {
+        let default = Cred::acquire(None, None, self.usage()?, None)?;
+        self.is_for_name(&default.name()?)
+    }
 }
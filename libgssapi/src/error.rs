@@ -1,4 +1,8 @@
-use crate::util::Buf;
+use crate::{
+    credential::CredUsage,
+    oid::{indicate_mechs, Oid},
+    util::Buf,
+};
 use libgssapi_sys::{
     gss_OID_desc, gss_display_status, OM_uint32, GSS_C_CALLING_ERROR_OFFSET,
     GSS_C_GSS_CODE, GSS_C_MECH_CODE, GSS_C_ROUTINE_ERROR_OFFSET, GSS_S_COMPLETE,
@@ -52,6 +56,94 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// Supplementary information about a per message token, the
+    /// message is valid but it wasn't received in the expected order.
+    /// These are only reported if the context was established with
+    /// `GSS_C_REPLAY_FLAG` or `GSS_C_SEQUENCE_FLAG`.
+    pub struct SupplementaryFlags: u32 {
+        /// the token is a duplicate of one already received
+        const GSS_S_DUPLICATE_TOKEN = _GSS_S_DUPLICATE_TOKEN;
+        /// the token is too old to check for duplication
+        const GSS_S_OLD_TOKEN = _GSS_S_OLD_TOKEN;
+        /// a later token has already been received
+        const GSS_S_UNSEQ_TOKEN = _GSS_S_UNSEQ_TOKEN;
+        /// an expected earlier token has not been received
+        const GSS_S_GAP_TOKEN = _GSS_S_GAP_TOKEN;
+    }
+}
+
+impl MajorFlags {
+    /// The names of the status codes in self. The calling and routine
+    /// errors are numbers rather than bits, so they must be matched
+    /// exactly, not with `contains`.
+    fn names(&self) -> Vec<&'static str> {
+        const CALLING: &[(MajorFlags, &str)] = &[
+            (
+                MajorFlags::GSS_S_CALL_INACCESSIBLE_READ,
+                "GSS_S_CALL_INACCESSIBLE_READ",
+            ),
+            (
+                MajorFlags::GSS_S_CALL_INACCESSIBLE_WRITE,
+                "GSS_S_CALL_INACCESSIBLE_WRITE",
+            ),
+            (
+                MajorFlags::GSS_S_CALL_BAD_STRUCTURE,
+                "GSS_S_CALL_BAD_STRUCTURE",
+            ),
+        ];
+        const ROUTINE: &[(MajorFlags, &str)] = &[
+            (MajorFlags::GSS_S_BAD_MECH, "GSS_S_BAD_MECH"),
+            (MajorFlags::GSS_S_BAD_NAME, "GSS_S_BAD_NAME"),
+            (MajorFlags::GSS_S_BAD_NAMETYPE, "GSS_S_BAD_NAMETYPE"),
+            (MajorFlags::GSS_S_BAD_BINDINGS, "GSS_S_BAD_BINDINGS"),
+            (MajorFlags::GSS_S_BAD_STATUS, "GSS_S_BAD_STATUS"),
+            (MajorFlags::GSS_S_BAD_SIG, "GSS_S_BAD_SIG"),
+            (MajorFlags::GSS_S_BAD_MIC, "GSS_S_BAD_MIC"),
+            (MajorFlags::GSS_S_NO_CRED, "GSS_S_NO_CRED"),
+            (MajorFlags::GSS_S_NO_CONTEXT, "GSS_S_NO_CONTEXT"),
+            (MajorFlags::GSS_S_DEFECTIVE_TOKEN, "GSS_S_DEFECTIVE_TOKEN"),
+            (
+                MajorFlags::GSS_S_DEFECTIVE_CREDENTIAL,
+                "GSS_S_DEFECTIVE_CREDENTIAL",
+            ),
+            (
+                MajorFlags::GSS_S_CREDENTIALS_EXPIRED,
+                "GSS_S_CREDENTIALS_EXPIRED",
+            ),
+            (MajorFlags::GSS_S_CONTEXT_EXPIRED, "GSS_S_CONTEXT_EXPIRED"),
+            (MajorFlags::GSS_S_FAILURE, "GSS_S_FAILURE"),
+            (MajorFlags::GSS_S_BAD_QOP, "GSS_S_BAD_QOP"),
+            (MajorFlags::GSS_S_UNAUTHORIZED, "GSS_S_UNAUTHORIZED"),
+            (MajorFlags::GSS_S_UNAVAILABLE, "GSS_S_UNAVAILABLE"),
+            (
+                MajorFlags::GSS_S_DUPLICATE_ELEMENT,
+                "GSS_S_DUPLICATE_ELEMENT",
+            ),
+            (MajorFlags::GSS_S_NAME_NOT_MN, "GSS_S_NAME_NOT_MN"),
+            (MajorFlags::GSS_S_BAD_MECH_ATTR, "GSS_S_BAD_MECH_ATTR"),
+        ];
+        const SUPPLEMENTARY: &[(MajorFlags, &str)] = &[
+            (MajorFlags::GSS_S_CONTINUE_NEEDED, "GSS_S_CONTINUE_NEEDED"),
+            (MajorFlags::GSS_S_DUPLICATE_TOKEN, "GSS_S_DUPLICATE_TOKEN"),
+            (MajorFlags::GSS_S_OLD_TOKEN, "GSS_S_OLD_TOKEN"),
+            (MajorFlags::GSS_S_UNSEQ_TOKEN, "GSS_S_UNSEQ_TOKEN"),
+            (MajorFlags::GSS_S_GAP_TOKEN, "GSS_S_GAP_TOKEN"),
+        ];
+        let calling =
+            self.bits() & (_GSS_C_CALLING_ERROR_MASK << GSS_C_CALLING_ERROR_OFFSET);
+        let routine =
+            self.bits() & (_GSS_C_ROUTINE_ERROR_MASK << GSS_C_ROUTINE_ERROR_OFFSET);
+        CALLING
+            .iter()
+            .filter(|(f, _)| f.bits() == calling)
+            .chain(ROUTINE.iter().filter(|(f, _)| f.bits() == routine))
+            .chain(SUPPLEMENTARY.iter().filter(|(f, _)| self.contains(*f)))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
 pub(crate) fn gss_error(x: OM_uint32) -> OM_uint32 {
     x & ((_GSS_C_CALLING_ERROR_MASK << GSS_C_CALLING_ERROR_OFFSET)
         | (_GSS_C_ROUTINE_ERROR_MASK << GSS_C_ROUTINE_ERROR_OFFSET))
@@ -63,14 +155,147 @@ enum ErrorComponent {
     Minor = GSS_C_MECH_CODE as isize,
 }
 
+/// Problems detected by this library itself, rather than reported
+/// by gssapi.
+#[derive(Clone, Copy, Debug)]
+pub enum ErrorDetail {
+    /// gssapi gave us a credential usage that we don't know about
+    UnknownCredUsage(i32),
+    /// none of the preferred mechanisms are supported by both the
+    /// credential and the name, see `negotiate_mech`
+    NoCommonMechanism,
+    /// the credential can't be used for, or restricted to, the
+    /// requested usage, see `Cred::with_usage` and `Cred::acquire`
+    UsageUnavailable(CredUsage),
+    /// the credential didn't come from `Cred::acquire` or
+    /// `Cred::acquire_from`, so it can't be acquired again, see
+    /// `Cred::refresh`
+    NotReacquirable,
+}
+
+impl fmt::Display for ErrorDetail {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorDetail::UnknownCredUsage(u) => {
+                write!(f, "unknown credential usage {}", u)
+            }
+            ErrorDetail::NoCommonMechanism => write!(f, "no common mechanism"),
+            ErrorDetail::UsageUnavailable(u) => {
+                write!(f, "credential usage {:?} unavailable", u)
+            }
+            ErrorDetail::NotReacquirable => {
+                write!(f, "credential can't be re-acquired")
+            }
+        }
+    }
+}
+
+/// Common Kerberos failures, classified from the com_err code in the
+/// minor status. See `Error::krb5_kind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Krb5ErrorKind {
+    /// The ticket has expired (KRB5KRB_AP_ERR_TKT_EXPIRED)
+    TicketExpired,
+    /// The clocks of the client and the server are too far apart
+    /// (KRB5KRB_AP_ERR_SKEW)
+    ClockSkew,
+    /// Preauthentication failed, usually a wrong password
+    /// (KRB5KDC_ERR_PREAUTH_FAILED)
+    PreauthFailed,
+    /// The client's password or key has expired (KRB5KDC_ERR_KEY_EXP)
+    KeyExpired,
+    /// The client principal isn't known to the KDC
+    /// (KRB5KDC_ERR_C_PRINCIPAL_UNKNOWN)
+    ClientNotFound,
+    /// The server principal isn't known to the KDC
+    /// (KRB5KDC_ERR_S_PRINCIPAL_UNKNOWN)
+    ServerNotFound,
+    /// There is no credentials cache, or no matching credentials in
+    /// it (KRB5_FCC_NOFILE, KRB5_CC_NOTFOUND)
+    NoCredentialsCache,
+    /// The keytab has no key for the service, or not the right
+    /// version of it (KRB5_KT_NOTFOUND, KRB5_KT_KVNONOTFOUND)
+    KeytabEntryNotFound,
+    /// The request is a replay (KRB5KRB_AP_ERR_REPEAT)
+    Replay,
+    /// No KDC could be reached for the realm (KRB5_KDC_UNREACH)
+    KdcUnreachable,
+    /// The realm could not be determined (KRB5_REALM_UNKNOWN)
+    RealmUnknown,
+    /// The network address of the realm's KDC could not be resolved
+    /// (KRB5_REALM_CANT_RESOLVE)
+    RealmCantResolve,
+    /// The KDC is temporarily unable to serve the request
+    /// (KRB5KDC_ERR_SVC_UNAVAILABLE)
+    KdcUnavailable,
+}
+
+// The com_err codes from the krb5 error table that `Krb5ErrorKind`
+// recognizes, named as in krb5.h. Every code in the table is its
+// offset from the table's base.
+const ERROR_TABLE_BASE_KRB5: i32 = -1765328384;
+const KRB5KDC_ERR_C_PRINCIPAL_UNKNOWN: i32 = ERROR_TABLE_BASE_KRB5 + 6;
+const KRB5KDC_ERR_S_PRINCIPAL_UNKNOWN: i32 = ERROR_TABLE_BASE_KRB5 + 7;
+const KRB5KDC_ERR_KEY_EXP: i32 = ERROR_TABLE_BASE_KRB5 + 23;
+const KRB5KDC_ERR_PREAUTH_FAILED: i32 = ERROR_TABLE_BASE_KRB5 + 24;
+const KRB5KDC_ERR_SVC_UNAVAILABLE: i32 = ERROR_TABLE_BASE_KRB5 + 29;
+const KRB5KRB_AP_ERR_TKT_EXPIRED: i32 = ERROR_TABLE_BASE_KRB5 + 32;
+const KRB5KRB_AP_ERR_REPEAT: i32 = ERROR_TABLE_BASE_KRB5 + 34;
+const KRB5KRB_AP_ERR_SKEW: i32 = ERROR_TABLE_BASE_KRB5 + 37;
+const KRB5_CC_NOTFOUND: i32 = ERROR_TABLE_BASE_KRB5 + 141;
+const KRB5_REALM_UNKNOWN: i32 = ERROR_TABLE_BASE_KRB5 + 154;
+const KRB5_KDC_UNREACH: i32 = ERROR_TABLE_BASE_KRB5 + 156;
+const KRB5_KT_NOTFOUND: i32 = ERROR_TABLE_BASE_KRB5 + 181;
+const KRB5_FCC_NOFILE: i32 = ERROR_TABLE_BASE_KRB5 + 195;
+const KRB5_REALM_CANT_RESOLVE: i32 = ERROR_TABLE_BASE_KRB5 + 220;
+const KRB5_KT_KVNONOTFOUND: i32 = ERROR_TABLE_BASE_KRB5 + 230;
+
+impl Krb5ErrorKind {
+    fn from_code(code: i32) -> Option<Krb5ErrorKind> {
+        match code {
+            KRB5KRB_AP_ERR_TKT_EXPIRED => Some(Krb5ErrorKind::TicketExpired),
+            KRB5KRB_AP_ERR_SKEW => Some(Krb5ErrorKind::ClockSkew),
+            KRB5KDC_ERR_PREAUTH_FAILED => Some(Krb5ErrorKind::PreauthFailed),
+            KRB5KDC_ERR_KEY_EXP => Some(Krb5ErrorKind::KeyExpired),
+            KRB5KDC_ERR_C_PRINCIPAL_UNKNOWN => Some(Krb5ErrorKind::ClientNotFound),
+            KRB5KDC_ERR_S_PRINCIPAL_UNKNOWN => Some(Krb5ErrorKind::ServerNotFound),
+            KRB5_FCC_NOFILE | KRB5_CC_NOTFOUND => Some(Krb5ErrorKind::NoCredentialsCache),
+            KRB5_KT_NOTFOUND | KRB5_KT_KVNONOTFOUND => {
+                Some(Krb5ErrorKind::KeytabEntryNotFound)
+            }
+            KRB5KRB_AP_ERR_REPEAT => Some(Krb5ErrorKind::Replay),
+            KRB5_KDC_UNREACH => Some(Krb5ErrorKind::KdcUnreachable),
+            KRB5_REALM_UNKNOWN => Some(Krb5ErrorKind::RealmUnknown),
+            KRB5_REALM_CANT_RESOLVE => Some(Krb5ErrorKind::RealmCantResolve),
+            KRB5KDC_ERR_SVC_UNAVAILABLE => Some(Krb5ErrorKind::KdcUnavailable),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Error {
     pub major: MajorFlags,
     pub minor: u32,
+    pub detail: Option<ErrorDetail>,
+    /// The mechanism that reported the error, if it's known. This is
+    /// needed to interpret `minor`, if it's `None` then the default
+    /// mechanism is assumed. An `Oid` borrows the memory it points to,
+    /// so this must only hold oids pointing to `'static` data, e.g.
+    /// one of our constants, one interned by `Oid::from_bytes`, or a
+    /// mechanism oid owned by the library, never one the caller lent
+    /// us.
+    pub mech: Option<Oid>,
 }
 
 impl Error {
-    fn fmt_code(f: &mut fmt::Formatter<'_>, code: u32, ctype: ErrorComponent) -> fmt::Result {
+    // the messages gssapi has for `code`, or None if it has none
+    fn try_status_messages(
+        code: u32,
+        ctype: ErrorComponent,
+        mech: Option<&Oid>,
+    ) -> Option<Vec<String>> {
+        let mut messages = Vec::new();
         let mut message_context: OM_uint32 = 0;
         loop {
             let mut minor = GSS_S_COMPLETE as OM_uint32;
@@ -80,35 +305,216 @@ impl Error {
                     &mut minor as *mut OM_uint32,
                     code,
                     ctype as c_int,
-                    ptr::null_mut::<gss_OID_desc>(),
+                    match mech {
+                        None => ptr::null_mut::<gss_OID_desc>(),
+                        Some(mech) => mech.to_c(),
+                    },
                     &mut message_context as *mut OM_uint32,
                     buf.to_c(),
                 )
             };
             if major == GSS_S_COMPLETE || major == _GSS_S_CONTINUE_NEEDED {
-                let s = String::from_utf8_lossy(&*buf);
-                let res = match ctype {
-                    ErrorComponent::Major => write!(f, "{}", s),
-                    ErrorComponent::Minor => write!(f, " ({})", s),
-                };
-                res?
+                if !buf.is_empty() {
+                    messages.push(String::from_utf8_lossy(&buf).into_owned());
+                }
             } else {
-                write!(f, "unknown GSSAPI({:?}) error code({})\n", ctype, code)?;
-                break;
+                return None;
             }
             if message_context == 0 {
                 break;
             }
         }
-        Ok(())
+        if messages.is_empty() {
+            None
+        } else {
+            Some(messages)
+        }
+    }
+
+    fn unknown_status(code: u32, ctype: ErrorComponent) -> Vec<String> {
+        vec![format!("unknown GSSAPI({:?}) error code({})", ctype, code)]
+    }
+
+    fn status_messages(
+        code: u32,
+        ctype: ErrorComponent,
+        mech: Option<&Oid>,
+    ) -> Vec<String> {
+        Error::try_status_messages(code, ctype, mech)
+            .unwrap_or_else(|| Error::unknown_status(code, ctype))
+    }
+
+    fn major_messages(&self) -> Vec<String> {
+        Error::status_messages(self.major.bits(), ErrorComponent::Major, None)
+    }
+
+    // if we don't know the mechanism try each one the library
+    // supports, then the default. A mechanism that doesn't know the
+    // code may still answer with a message that just echoes it (MIT
+    // says "Unknown code ..."), so that doesn't count as knowing it.
+    fn minor_messages(&self) -> Vec<String> {
+        let (code, ctype) = (self.minor, ErrorComponent::Minor);
+        let known = |messages: &Vec<String>| {
+            !messages.iter().all(|m| m.starts_with("Unknown code"))
+        };
+        match &self.mech {
+            Some(mech) => Error::status_messages(code, ctype, Some(mech)),
+            None => indicate_mechs()
+                .ok()
+                .and_then(|mechs| {
+                    mechs.iter().find_map(|mech| {
+                        Error::try_status_messages(code, ctype, Some(mech)).filter(known)
+                    })
+                })
+                .unwrap_or_else(|| Error::status_messages(code, ctype, None)),
+        }
+    }
+
+    /// If the minor status is one of the common Kerberos errors,
+    /// return which one, so you can e.g. resync and retry on clock
+    /// skew. com_err codes are unique across mechanisms, so this is
+    /// safe to call on any error.
+    pub fn krb5_kind(&self) -> Option<Krb5ErrorKind> {
+        Krb5ErrorKind::from_code(self.minor as i32)
+    }
+
+    /// Return true if the error was caused by the clocks of the client
+    /// and the server being too far apart (`KRB5KRB_AP_ERR_SKEW`),
+    /// e.g. to tell the user to fix their clock.
+    pub fn is_clock_skew(&self) -> bool {
+        self.krb5_kind() == Some(Krb5ErrorKind::ClockSkew)
+    }
+
+    /// Return true if the error is likely transient, so the same
+    /// operation may succeed if retried later. Currently this is only
+    /// the case for the krb5 errors that mean the KDC couldn't be
+    /// reached or couldn't serve us (`KdcUnreachable`,
+    /// `RealmCantResolve` and `KdcUnavailable`), errors from other
+    /// mechanisms are never considered transient. Errors like a wrong
+    /// password or an unknown principal are permanent.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self.krb5_kind(),
+            Some(Krb5ErrorKind::KdcUnreachable)
+                | Some(Krb5ErrorKind::RealmCantResolve)
+                | Some(Krb5ErrorKind::KdcUnavailable)
+        )
+    }
+
+    /// All the messages gssapi has for the major status, followed by
+    /// the detail if there is one, otherwise all the messages the
+    /// mechanism has for the minor status (if any).
+    pub fn messages(&self) -> Vec<String> {
+        let mut messages = self.major_messages();
+        if let Some(detail) = self.detail {
+            messages.push(detail.to_string());
+        } else if self.minor != 0 {
+            messages.extend(self.minor_messages());
+        }
+        messages
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        Error::fmt_code(f, self.major.bits(), ErrorComponent::Major)?;
-        Ok(Error::fmt_code(f, self.minor, ErrorComponent::Minor)?)
+        write!(
+            f,
+            "{} (major={:#x}): {}",
+            self.major.names().join(" | "),
+            self.major.bits(),
+            self.major_messages().join(", ")
+        )?;
+        match self.detail {
+            Some(detail) => write!(f, " ({})", detail),
+            None if self.minor == 0 => Ok(()),
+            None => write!(f, " ({})", self.minor_messages().join(", ")),
+        }
     }
 }
 
 impl error::Error for Error {}
+
+/// An error from `ClientCtx::step` or `ServerCtx::step`. Besides the
+/// error itself it holds the error token the mechanism produced, if
+/// it produced one, which should be sent to the peer so it learns why
+/// establishment failed.
+#[derive(Debug)]
+pub struct StepError {
+    pub error: Error,
+    pub token: Option<Buf>,
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl error::Error for StepError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Drop the error token, for callers that have no peer to send it to
+impl From<StepError> for Error {
+    fn from(e: StepError) -> Error {
+        e.error
+    }
+}
+
+/// An error that happened before there was a token to send, e.g.
+/// while acquiring credentials
+impl From<Error> for StepError {
+    fn from(error: Error) -> StepError {
+        StepError { error, token: None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oid::GSS_MECH_KRB5;
+
+    fn krb5_error(code: i32) -> Error {
+        Error {
+            major: MajorFlags::GSS_S_FAILURE,
+            minor: code as u32,
+            detail: None,
+            mech: Some(GSS_MECH_KRB5),
+        }
+    }
+
+    #[test]
+    fn transient_errors() {
+        let unreachable = krb5_error(KRB5_KDC_UNREACH);
+        assert_eq!(unreachable.krb5_kind(), Some(Krb5ErrorKind::KdcUnreachable));
+        assert!(unreachable.is_transient());
+        let cant_resolve = krb5_error(KRB5_REALM_CANT_RESOLVE);
+        assert_eq!(cant_resolve.krb5_kind(), Some(Krb5ErrorKind::RealmCantResolve));
+        assert!(cant_resolve.is_transient());
+        let bad_password = krb5_error(KRB5KDC_ERR_PREAUTH_FAILED);
+        assert_eq!(bad_password.krb5_kind(), Some(Krb5ErrorKind::PreauthFailed));
+        assert!(!bad_password.is_transient());
+        let unknown_principal = krb5_error(KRB5KDC_ERR_C_PRINCIPAL_UNKNOWN);
+        assert_eq!(
+            unknown_principal.krb5_kind(),
+            Some(Krb5ErrorKind::ClientNotFound)
+        );
+        assert!(!unknown_principal.is_transient());
+        let other = krb5_error(0);
+        assert_eq!(other.krb5_kind(), None);
+        assert!(!other.is_transient());
+    }
+
+    #[test]
+    fn krb5_codes() {
+        // the values of the same macros in MIT's krb5.h
+        assert_eq!(KRB5KDC_ERR_C_PRINCIPAL_UNKNOWN, -1765328378);
+        assert_eq!(KRB5KDC_ERR_PREAUTH_FAILED, -1765328360);
+        assert_eq!(KRB5KRB_AP_ERR_SKEW, -1765328347);
+        assert_eq!(KRB5_KDC_UNREACH, -1765328228);
+        assert_eq!(KRB5_FCC_NOFILE, -1765328189);
+        assert_eq!(KRB5_KT_KVNONOTFOUND, -1765328154);
+    }
+}
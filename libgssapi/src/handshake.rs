@@ -0,0 +1,150 @@
+//! Drive context establishment over an async stream.
+//!
+//! Tokens are framed with a 4 byte big endian length followed by the
+//! token itself. The client sends the first token, after that each
+//! side sends a token whenever `step` produces one, until both sides
+//! are complete. This is simple enough to implement on a non Rust
+//! peer.
+use crate::context::{ClientCtx, SecurityContext, ServerCtx};
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// The largest token we are willing to read from the peer.
+pub const MAX_TOKEN_SIZE: u32 = 1024 * 1024;
+
+fn gss_err(e: crate::error::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+async fn read_token<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<Vec<u8>> {
+    let len = stream.read_u32().await?;
+    if len > MAX_TOKEN_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("token too large {}", len),
+        ));
+    }
+    let mut tok = vec![0; len as usize];
+    stream.read_exact(&mut tok).await?;
+    Ok(tok)
+}
+
+async fn write_token<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    tok: &[u8],
+) -> io::Result<()> {
+    stream.write_u32(tok.len() as u32).await?;
+    stream.write_all(tok).await?;
+    stream.flush().await
+}
+
+impl ClientCtx {
+    /// Establish the context with a server calling
+    /// `ServerCtx::handshake` on the other end of `stream`. gssapi
+    /// errors are returned as `io::ErrorKind::Other` errors wrapping
+    /// the `Error`, if the mechanism produced an error token it is
+    /// sent to the server first.
+    pub async fn handshake<S>(mut self, stream: &mut S) -> io::Result<ClientCtx>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut server_tok: Option<Vec<u8>> = None;
+        loop {
+            match self.step(server_tok.as_deref()) {
+                Err(e) => {
+                    if let Some(tok) = e.token {
+                        write_token(stream, &tok).await?;
+                    }
+                    return Err(gss_err(e.error));
+                }
+                Ok(None) => break,
+                Ok(Some(tok)) => {
+                    write_token(stream, &tok).await?;
+                    if self.is_complete() {
+                        break;
+                    }
+                    server_tok = Some(read_token(stream).await?);
+                }
+            }
+        }
+        Ok(self)
+    }
+}
+
+impl ServerCtx {
+    /// Establish the context with a client calling
+    /// `ClientCtx::handshake` on the other end of `stream`. gssapi
+    /// errors are returned as `io::ErrorKind::Other` errors wrapping
+    /// the `Error`, if the mechanism produced an error token it is
+    /// sent to the client first.
+    pub async fn handshake<S>(mut self, stream: &mut S) -> io::Result<ServerCtx>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        loop {
+            let client_tok = read_token(stream).await?;
+            match self.step(&client_tok) {
+                Err(e) => {
+                    if let Some(tok) = e.token {
+                        write_token(stream, &tok).await?;
+                    }
+                    return Err(gss_err(e.error));
+                }
+                Ok(None) => break,
+                Ok(Some(tok)) => {
+                    write_token(stream, &tok).await?;
+                    if self.is_complete() {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::Future;
+    use tokio::{io::duplex, runtime::Builder};
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        Builder::new_current_thread().build().unwrap().block_on(f)
+    }
+
+    #[test]
+    fn length_prefix() {
+        block_on(async {
+            let (mut a, mut b) = duplex(64);
+            write_token(&mut a, b"hello").await.unwrap();
+            let mut raw = [0; 9];
+            b.read_exact(&mut raw).await.unwrap();
+            assert_eq!(&raw, b"\0\0\0\x05hello");
+            write_token(&mut a, b"again").await.unwrap();
+            assert_eq!(read_token(&mut b).await.unwrap(), b"again");
+        })
+    }
+
+    #[test]
+    fn too_large() {
+        block_on(async {
+            let (mut a, mut b) = duplex(64);
+            a.write_u32(MAX_TOKEN_SIZE + 1).await.unwrap();
+            let e = read_token(&mut b).await.unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        })
+    }
+
+    #[test]
+    fn eof_mid_frame() {
+        block_on(async {
+            let (mut a, mut b) = duplex(64);
+            a.write_u32(10).await.unwrap();
+            a.write_all(b"abc").await.unwrap();
+            drop(a);
+            let e = read_token(&mut b).await.unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        })
+    }
+}
@@ -43,7 +43,7 @@
 //!     let server_cred = Cred::acquire(
 //!         Some(&cname), None, CredUsage::Accept, Some(desired_mechs)
 //!     )?;
-//!     Ok((ServerCtx::new(server_cred), cname))
+//!     Ok((ServerCtx::new(Some(server_cred), None), cname))
 //! }
 //! 
 //! fn setup_client_ctx(
@@ -54,7 +54,8 @@
 //!         None, None, CredUsage::Initiate, Some(&desired_mechs)
 //!     )?;
 //!     Ok(ClientCtx::new(
-//!         client_cred, service_name, CtxFlags::GSS_C_MUTUAL_FLAG, Some(&GSS_MECH_KRB5)
+//!         client_cred, service_name, CtxFlags::GSS_C_MUTUAL_FLAG,
+//!         Some(&GSS_MECH_KRB5), None
 //!     ))
 //! }
 //! 
@@ -68,7 +69,7 @@
 //!     let mut client_ctx = setup_client_ctx(cname, &desired_mechs)?;
 //!     let mut server_tok: Option<Buf> = None;
 //!     loop {
-//!         match client_ctx.step(server_tok.as_ref().map(|b| &**b), None)? {
+//!         match client_ctx.step(server_tok.as_ref().map(|b| &**b))? {
 //!             None => break,
 //!             Some(client_tok) => match server_ctx.step(&*client_tok)? {
 //!                 None => break,
@@ -91,4 +92,6 @@ pub mod util;
 pub mod name;
 pub mod credential;
 pub mod context;
+#[cfg(feature = "tokio")]
+pub mod handshake;
  
@@ -1,14 +1,125 @@
 use crate::{
     error::{Error, MajorFlags},
-    util::{Buf, BufRef},
-    oid::Oid,
+    oid::{
+        Oid, OidSet, GSS_NT_ANONYMOUS, GSS_NT_COMPOSITE_EXPORT, GSS_NT_EXPORT_NAME,
+        GSS_NT_HOSTBASED_SERVICE, GSS_NT_USER_NAME,
+    },
+    util::{Buf, BufRef, BufferSet},
 };
 use libgssapi_sys::{
-    gss_OID, gss_OID_desc, gss_canonicalize_name, gss_display_name, gss_duplicate_name,
-    gss_import_name, gss_name_struct, gss_name_t, gss_release_name, gss_export_name,
-    OM_uint32, GSS_S_COMPLETE,
+    gss_OID, gss_OID_desc, gss_OID_set, gss_OID_set_desc, gss_any, gss_any_t,
+    gss_buffer_set_desc, gss_buffer_set_t, gss_canonicalize_name, gss_compare_name,
+    gss_delete_name_attribute, gss_display_name, gss_display_name_ext,
+    gss_duplicate_name, gss_export_name, gss_export_name_composite,
+    gss_get_name_attribute, gss_import_name, gss_inquire_mechs_for_name,
+    gss_inquire_name, gss_localname, gss_map_name_to_any, gss_name_struct, gss_name_t,
+    gss_release_any_name_mapping, gss_release_name, gss_set_name_attribute, OM_uint32,
+    GSS_S_COMPLETE,
 };
-use std::{ptr, fmt};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{ffi, fmt, mem, os::raw::c_int, ptr};
+
+/// One value of a name attribute (RFC 6680)
+#[derive(Debug)]
+pub struct NameAttribute {
+    /// true if the mechanism verified the value, never trust an
+    /// attribute that isn't authenticated
+    pub authenticated: bool,
+    /// true if this is the complete set of values of the attribute
+    pub complete: bool,
+    pub value: Buf,
+    /// a human readable form of the value, possibly empty
+    pub display_value: String,
+}
+
+/// Information about a name, see `Name::info`
+#[derive(Debug)]
+pub struct NameInfo {
+    /// true if the name is a mechanism name (MN)
+    pub mechanism_name: bool,
+    /// if the name is a mechanism name, the mechanism
+    pub mechanism: Option<&'static Oid>,
+    /// the names of all the attributes of the name
+    pub attributes: BufferSet,
+}
+
+/// The exported form of a name, as produced by `Name::export`,
+/// suitable for storing. With the `serde` feature this is serializable,
+/// deserializing it only checks that it is well formed serde data, the
+/// contents are not parsed until `import` is called.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename = "gss_exported_name"))]
+pub struct ExportedName {
+    token: Vec<u8>,
+}
+
+impl From<Vec<u8>> for ExportedName {
+    fn from(token: Vec<u8>) -> Self {
+        ExportedName { token }
+    }
+}
+
+impl ExportedName {
+    /// Export `name`, which must be a mechanism name, see
+    /// `Name::export`.
+    pub fn new(name: &Name) -> Result<Self, Error> {
+        Ok(ExportedName {
+            token: name.export()?.to_vec(),
+        })
+    }
+
+    /// The raw exported name token
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.token
+    }
+
+    /// Import the name, this will fail if the token isn't a valid
+    /// exported name.
+    pub fn import(&self) -> Result<Name, Error> {
+        Name::import_export_token(&self.token)
+    }
+}
+
+/// A mechanism specific native object for a name, e.g. a
+/// `krb5_principal`, see `Name::map_to_any`. The object is released
+/// when this is dropped.
+pub struct NameMapping<'a> {
+    name: &'a Name,
+    type_id: Vec<u8>,
+    any: gss_any_t,
+}
+
+impl<'a> Drop for NameMapping<'a> {
+    fn drop(&mut self) {
+        let mut _minor = GSS_S_COMPLETE;
+        let mut type_id = BufRef::from(&self.type_id[..]);
+        let _major = unsafe {
+            gss_release_any_name_mapping(
+                &mut _minor as *mut OM_uint32,
+                self.name.to_c(),
+                type_id.to_c(),
+                &mut self.any as *mut gss_any_t,
+            )
+        };
+    }
+}
+
+impl<'a> fmt::Debug for NameMapping<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "NameMapping({:?})", self.any)
+    }
+}
+
+impl<'a> NameMapping<'a> {
+    /// The native object. It is only valid as long as this mapping
+    /// is alive, and what it points to depends on the type id the
+    /// mapping was created with, so using it is up to you.
+    pub fn as_ptr(&self) -> *mut ffi::c_void {
+        self.any as *mut ffi::c_void
+    }
+}
 
 pub struct Name(gss_name_t);
 
@@ -31,25 +142,30 @@ impl Drop for Name {
 
 impl fmt::Debug for Name {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        let mut minor = GSS_S_COMPLETE;
-        let mut buf = Buf::empty();
-        let mut oid = ptr::null_mut::<gss_OID_desc>();
-        let major = unsafe {
-            gss_display_name(
-                &mut minor as *mut OM_uint32,
-                self.to_c(),
-                buf.to_c(),
-                &mut oid as *mut gss_OID,
-            )
-        };
-        if major == GSS_S_COMPLETE {
-            write!(f, "{}", String::from_utf8_lossy(&*buf))
-        } else {
-            write!(f, "<name can't be displayed>")
+        match self.display() {
+            Ok((name, _)) => write!(f, "{}", name),
+            Err(_) => write!(f, "<name can't be displayed>"),
         }
     }
 }
 
+/// Names are compared with `Name::compare`, if the comparison fails
+/// (e.g. because the names are of incompatible types) then they are
+/// considered not equal.
+impl PartialEq for Name {
+    fn eq(&self, other: &Name) -> bool {
+        self.compare(other).unwrap_or(false)
+    }
+}
+
+/// Cloning a name calls `Name::duplicate`, and panics if that
+/// fails. Use `duplicate` directly if you want to handle the error.
+impl Clone for Name {
+    fn clone(&self) -> Self {
+        self.duplicate().expect("failed to duplicate name")
+    }
+}
+
 impl fmt::Display for Name {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         fmt::Debug::fmt(self, f)
@@ -65,6 +181,36 @@ impl Name {
     pub(crate) unsafe fn from_c(ptr: gss_name_t) -> Self {
         Name(ptr)
     }
+
+    /// Wrap a raw gssapi name handle, e.g. one obtained from another
+    /// library. Ownership of the handle moves to the returned `Name`,
+    /// which releases it with `gss_release_name` when dropped, so the
+    /// caller must not release or otherwise use it afterwards.
+    ///
+    /// # Safety
+    ///
+    /// `name` must be a valid name handle from the same gssapi
+    /// library this crate is linked with, and nothing else may own
+    /// it.
+    pub unsafe fn from_raw(name: gss_name_t) -> Self {
+        Name(name)
+    }
+
+    /// Get the raw gssapi name handle, e.g. to pass to another
+    /// library. The handle is still owned by this `Name`, it becomes
+    /// invalid when the `Name` is dropped and must not be released by
+    /// the caller.
+    pub fn as_raw(&self) -> gss_name_t {
+        self.0
+    }
+
+    /// Give up ownership of the raw gssapi name handle, the caller
+    /// becomes responsible for releasing it with `gss_release_name`.
+    pub fn into_raw(self) -> gss_name_t {
+        let name = self.0;
+        mem::forget(self);
+        name
+    }
     
     /// parse the specified bytes as a gssapi name, with optional
     /// `kind` e.g. `GSS_NT_HOSTBASED_SERVICE` or
@@ -89,14 +235,17 @@ impl Name {
         } else {
             Err(Error {
                 major: unsafe { MajorFlags::from_bits_unchecked(major) },
-                minor
+                minor,
+                detail: None,
+                mech: None,
             })
         }
     }
 
     /// canonicalize a name for the specified mechanism (or the
     /// default mechanism if not specified). This makes a copy of the
-    /// name.
+    /// name. The result is a mechanism name (MN), and so it is
+    /// suitable for `export`.
     pub fn canonicalize(&self, mech: Option<&Oid>) -> Result<Self, Error> {
         let mut out = ptr::null_mut::<gss_name_struct>();
         let mut minor = GSS_S_COMPLETE;
@@ -116,30 +265,376 @@ impl Name {
         } else {
             Err(Error {
                 major: unsafe { MajorFlags::from_bits_unchecked(major) },
-                minor
+                minor,
+                detail: None,
+                mech: None,
             })
         }
     }
 
+    /// Import a name from a token produced by `export` or
+    /// `export_composite`, the kind of token is detected from its
+    /// header. The result is a mechanism name, so it can be compared
+    /// or exported again without canonicalizing it.
+    pub fn import_export_token(token: &[u8]) -> Result<Self, Error> {
+        // RFC 6680 composite tokens start with 04 02 instead of 04 01
+        if token.starts_with(b"\x04\x02") {
+            Name::new(token, Some(&GSS_NT_COMPOSITE_EXPORT))
+        } else {
+            Name::new(token, Some(&GSS_NT_EXPORT_NAME))
+        }
+    }
+
+    /// Import the host based service name `service@host`, e.g.
+    /// `Name::hostbased_service("HTTP", "www.example.com")`
+    pub fn hostbased_service(service: &str, host: &str) -> Result<Self, Error> {
+        let name = format!("{}@{}", service, host);
+        Name::new(name.as_bytes(), Some(&GSS_NT_HOSTBASED_SERVICE))
+    }
+
+    /// Import the user name `name`, e.g. `Name::user("alice")` or, with
+    /// krb5, `Name::user("alice@EXAMPLE.COM")`
+    pub fn user(name: &str) -> Result<Self, Error> {
+        Name::new(name.as_bytes(), Some(&GSS_NT_USER_NAME))
+    }
+
+    /// The anonymous name. An initiator can acquire credentials for
+    /// it and request `GSS_C_ANON_FLAG` to establish a context without
+    /// revealing its identity, with krb5 this needs anonymous PKINIT
+    /// to be set up on the KDC.
+    pub fn anonymous() -> Result<Self, Error> {
+        Name::new(b"", Some(&GSS_NT_ANONYMOUS))
+    }
+
     /// Produce a contiguous string representation of a canonicalized
     /// name suitable for direct comparison. You must either use a
-    /// canonical name, or call canonicalize before using this method.
+    /// canonical name, or call canonicalize before using this method,
+    /// otherwise this fails with `GSS_S_NAME_NOT_MN`.
     pub fn export(&self) -> Result<Buf, Error> {
+        let mut out = Buf::empty();
+        let mut minor = GSS_S_COMPLETE;
+        let major =
+            unsafe { gss_export_name(&mut minor as *mut OM_uint32, self.0, out.to_c()) };
+        if major == GSS_S_COMPLETE {
+            Ok(out)
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: None,
+            })
+        }
+    }
+
+    /// The same as `export`, except the token also carries the
+    /// name's attributes (RFC 6680), e.g. authenticated group
+    /// membership, so they survive `import_export_token`.
+    pub fn export_composite(&self) -> Result<Buf, Error> {
         let mut out = Buf::empty();
         let mut minor = GSS_S_COMPLETE;
         let major = unsafe {
-            gss_export_name(
+            gss_export_name_composite(&mut minor as *mut OM_uint32, self.0, out.to_c())
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(out)
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: None,
+            })
+        }
+    }
+
+    /// Get the human readable form of the name, along with the type
+    /// of the name, e.g. `GSS_NT_KRB5_PRINCIPAL`. The name is
+    /// returned as raw bytes, see `display` if you want a string.
+    pub fn display_bytes(&self) -> Result<(Buf, Option<&'static Oid>), Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut buf = Buf::empty();
+        let mut oid = ptr::null_mut::<gss_OID_desc>();
+        let major = unsafe {
+            gss_display_name(
                 &mut minor as *mut OM_uint32,
-                self.0,
-                out.to_c()
+                self.to_c(),
+                buf.to_c(),
+                &mut oid as *mut gss_OID,
             )
         };
         if major == GSS_S_COMPLETE {
-            Ok(out)
+            if oid.is_null() {
+                Ok((buf, None))
+            } else {
+                Ok((buf, Some(unsafe { Oid::from_c(oid) })))
+            }
         } else {
             Err(Error {
                 major: unsafe { MajorFlags::from_bits_unchecked(major) },
-                minor
+                minor,
+                detail: None,
+                mech: None,
+            })
+        }
+    }
+
+    /// The same as `display_bytes`, but the name is converted to a
+    /// string, replacing any invalid UTF-8 sequences.
+    pub fn display(&self) -> Result<(String, Option<&'static Oid>), Error> {
+        let (buf, kind) = self.display_bytes()?;
+        Ok((String::from_utf8_lossy(&*buf).into_owned(), kind))
+    }
+
+    /// Get the human readable form of the name in the syntax of the
+    /// name type `display_as_name_type` (RFC 6680), e.g.
+    /// `GSS_NT_USER_NAME`. If the mechanism can't display the name
+    /// that way this fails, usually with `GSS_S_UNAVAILABLE`. Invalid
+    /// UTF-8 sequences are replaced.
+    pub fn display_as(&self, display_as_name_type: &Oid) -> Result<String, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut buf = Buf::empty();
+        let major = unsafe {
+            gss_display_name_ext(
+                &mut minor as *mut OM_uint32,
+                self.to_c(),
+                display_as_name_type.to_c(),
+                buf.to_c(),
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(String::from_utf8_lossy(&buf).into_owned())
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: None,
+            })
+        }
+    }
+
+    /// Return true if this is the anonymous name, e.g. the source name
+    /// of a context the client established with `GSS_C_ANON_FLAG`
+    pub fn is_anonymous(&self) -> Result<bool, Error> {
+        let (_, kind) = self.display_bytes()?;
+        Ok(kind == Some(&GSS_NT_ANONYMOUS))
+    }
+
+    /// Compare two names according to the rules of their
+    /// mechanism. If the names are of incompatible types this fails
+    /// with `GSS_S_BAD_NAMETYPE`.
+    pub fn compare(&self, other: &Name) -> Result<bool, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut equal: c_int = 0;
+        let major = unsafe {
+            gss_compare_name(
+                &mut minor as *mut OM_uint32,
+                self.to_c(),
+                other.to_c(),
+                &mut equal as *mut c_int,
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(equal != 0)
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: None,
+            })
+        }
+    }
+
+    /// Return the set of mechanisms that can process this name.
+    pub fn mechanisms(&self) -> Result<OidSet, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut mechs = ptr::null_mut::<gss_OID_set_desc>();
+        let major = unsafe {
+            gss_inquire_mechs_for_name(
+                &mut minor as *mut OM_uint32,
+                self.to_c(),
+                &mut mechs as *mut gss_OID_set,
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(unsafe { OidSet::from_c(mechs) })
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: None,
+            })
+        }
+    }
+
+    /// Get information about the name, including the names of all
+    /// it's attributes (RFC 6680).
+    pub fn info(&self) -> Result<NameInfo, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut mn: c_int = 0;
+        let mut mech = ptr::null_mut::<gss_OID_desc>();
+        let mut attrs = ptr::null_mut::<gss_buffer_set_desc>();
+        let major = unsafe {
+            gss_inquire_name(
+                &mut minor as *mut OM_uint32,
+                self.to_c(),
+                &mut mn as *mut c_int,
+                &mut mech as *mut gss_OID,
+                &mut attrs as *mut gss_buffer_set_t,
+            )
+        };
+        if major != GSS_S_COMPLETE {
+            return Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: None,
+            });
+        }
+        let attributes = unsafe { BufferSet::from_c(attrs) };
+        Ok(NameInfo {
+            mechanism_name: mn != 0,
+            mechanism: if mech.is_null() {
+                None
+            } else {
+                Some(unsafe { Oid::from_c(mech) })
+            },
+            attributes,
+        })
+    }
+
+    /// Get the names of all the attributes of this name, which can
+    /// then be passed to `get_attribute`.
+    pub fn attributes(&self) -> Result<BufferSet, Error> {
+        Ok(self.info()?.attributes)
+    }
+
+    /// Get all the values of the attribute `attr` (RFC 6680), e.g. a
+    /// SAML assertion or a PAC derived group membership. If the name
+    /// doesn't have the attribute this fails with `GSS_S_UNAVAILABLE`.
+    pub fn get_attribute(&self, attr: &[u8]) -> Result<Vec<NameAttribute>, Error> {
+        let mut attr = BufRef::from(attr);
+        let mut values = Vec::new();
+        let mut more: c_int = -1;
+        while more != 0 {
+            let mut minor = GSS_S_COMPLETE;
+            let mut authenticated: c_int = 0;
+            let mut complete: c_int = 0;
+            let mut value = Buf::empty();
+            let mut display_value = Buf::empty();
+            let major = unsafe {
+                gss_get_name_attribute(
+                    &mut minor as *mut OM_uint32,
+                    self.to_c(),
+                    attr.to_c(),
+                    &mut authenticated as *mut c_int,
+                    &mut complete as *mut c_int,
+                    value.to_c(),
+                    display_value.to_c(),
+                    &mut more as *mut c_int,
+                )
+            };
+            if major != GSS_S_COMPLETE {
+                return Err(Error {
+                    major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                    minor,
+                    detail: None,
+                    mech: None,
+                });
+            }
+            values.push(NameAttribute {
+                authenticated: authenticated != 0,
+                complete: complete != 0,
+                value,
+                display_value: String::from_utf8_lossy(&display_value).into_owned(),
+            });
+        }
+        Ok(values)
+    }
+
+    /// Add `value` to the attribute `attr` of this name. If
+    /// `complete` is true then `value` is the complete set of values
+    /// of the attribute.
+    pub fn set_attribute(
+        &mut self,
+        complete: bool,
+        attr: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut attr = BufRef::from(attr);
+        let mut value = BufRef::from(value);
+        let major = unsafe {
+            gss_set_name_attribute(
+                &mut minor as *mut OM_uint32,
+                self.to_c(),
+                if complete { 1 } else { 0 },
+                attr.to_c(),
+                value.to_c(),
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(())
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: None,
+            })
+        }
+    }
+
+    /// Remove the attribute `attr` and all it's values from this
+    /// name.
+    pub fn delete_attribute(&mut self, attr: &[u8]) -> Result<(), Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut attr = BufRef::from(attr);
+        let major = unsafe {
+            gss_delete_name_attribute(
+                &mut minor as *mut OM_uint32,
+                self.to_c(),
+                attr.to_c(),
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(())
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: None,
+            })
+        }
+    }
+
+    /// Map this name to the name of a local user according to the
+    /// rules of `mech`, e.g. the krb5 auth_to_local rules. If the
+    /// mechanism can't map names to local users this fails with
+    /// `GSS_S_UNAVAILABLE`.
+    pub fn local_name(&self, mech: &Oid) -> Result<String, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut buf = Buf::empty();
+        let major = unsafe {
+            gss_localname(
+                &mut minor as *mut OM_uint32,
+                self.to_c(),
+                mech.to_c(),
+                buf.to_c(),
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(String::from_utf8_lossy(&buf).into_owned())
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: None,
             })
         }
     }
@@ -160,8 +655,65 @@ impl Name {
         } else {
             Err(Error {
                 major: unsafe { MajorFlags::from_bits_unchecked(major) },
-                minor
+                minor,
+                detail: None,
+                mech: None,
+            })
+        }
+    }
+
+    /// Map this name to a mechanism specific native object identified
+    /// by `type_id`, e.g. `krb5_principal` for the MIT krb5
+    /// mechanism. If `authenticated` is true only authenticated
+    /// information is used. This is mostly useful to hand the name to
+    /// a lower level library, the returned mapping only gives you a
+    /// raw pointer, and it's up to you to use it correctly.
+    pub fn map_to_any(
+        &self,
+        authenticated: bool,
+        type_id: &[u8],
+    ) -> Result<NameMapping<'_>, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut type_id_buf = BufRef::from(type_id);
+        let mut any = ptr::null_mut::<gss_any>();
+        let major = unsafe {
+            gss_map_name_to_any(
+                &mut minor as *mut OM_uint32,
+                self.to_c(),
+                if authenticated { 1 } else { 0 },
+                type_id_buf.to_c(),
+                &mut any as *mut gss_any_t,
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(NameMapping {
+                name: self,
+                type_id: Vec::from(type_id),
+                any,
+            })
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: None,
             })
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oid::{GSS_MECH_KRB5, GSS_NT_KRB5_PRINCIPAL};
+
+    #[test]
+    fn export_token_round_trip() {
+        let name = Name::new(b"user@EXAMPLE.COM", Some(&GSS_NT_KRB5_PRINCIPAL)).unwrap();
+        let cname = name.canonicalize(Some(&GSS_MECH_KRB5)).unwrap();
+        let token = cname.export().unwrap();
+        let imported = Name::import_export_token(&token).unwrap();
+        assert_eq!(imported, cname);
+        assert_eq!(&*imported.export().unwrap(), &*token);
+    }
+}
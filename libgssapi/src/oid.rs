@@ -1,21 +1,29 @@
 /// Oids are BER encoded and defined in the various RFCs. Oids are
 /// horrible. This module is horrible. I'm so pleased to share my
 /// horror with you.
-use crate::error::{Error, MajorFlags};
+use crate::{
+    error::{Error, MajorFlags},
+    util::{Buf, BufRef},
+};
 use libgssapi_sys::{
     gss_OID, gss_OID_desc, gss_OID_set, gss_OID_set_desc, gss_add_oid_set_member,
-    gss_create_empty_oid_set, gss_release_oid_set, gss_test_oid_set_member, OM_uint32,
-    GSS_S_COMPLETE,
+    gss_create_empty_oid_set, gss_display_mech_attr, gss_indicate_mechs,
+    gss_inquire_attrs_for_mech, gss_inquire_mech_for_saslname,
+    gss_inquire_names_for_mech, gss_inquire_saslname_for_mech, gss_oid_to_str,
+    gss_release_oid, gss_release_oid_set, gss_str_to_oid, gss_test_oid_set_member,
+    OM_uint32, GSS_S_COMPLETE,
 };
 use std::{
     self,
     cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd},
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt,
     hash::{Hash, Hasher},
     iter::{ExactSizeIterator, FromIterator, IntoIterator, Iterator},
     ops::{Deref, Index},
     ptr, slice,
+    str::FromStr,
+    sync::Mutex,
     os::raw::c_int,
 };
 
@@ -32,7 +40,7 @@ pub static GSS_NT_STRING_UID_NAME: Oid =
 pub static GSS_NT_HOSTBASED_SERVICE: Oid =
     Oid::from_slice(b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x01\x04");
 
-pub static GSS_NT_ANONYMOUS: Oid = Oid::from_slice(b"\x2b\x06\01\x05\x06\x03");
+pub static GSS_NT_ANONYMOUS: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x06\x03");
 
 pub static GSS_NT_EXPORT_NAME: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x06\x04");
 
@@ -41,6 +49,7 @@ pub static GSS_NT_COMPOSITE_EXPORT: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x06
 pub static GSS_NT_KRB5_PRINCIPAL: Oid =
     Oid::from_slice(b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x02\x01");
 
+#[cfg(any(feature = "mit", feature = "heimdal"))]
 pub static GSS_INQ_SSPI_SESSION_KEY: Oid =
     Oid::from_slice(b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x02\x05\x05");
 
@@ -60,6 +69,8 @@ pub static GSS_MECH_KRB5: Oid = Oid::from_slice(b"\x2a\x86\x48\x86\xf7\x12\x01\x
 
 pub static GSS_MECH_IAKERB: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x02\x05");
 
+pub static GSS_MECH_SPNEGO: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x05\x02");
+
 pub static GSS_KRB5_CRED_NO_CI_FLAGS_X: Oid =
     Oid::from_slice(b"\x2a\x85\x70\x2b\x0d\x1d");
 
@@ -70,6 +81,9 @@ pub(crate) const NO_OID: gss_OID = ptr::null_mut();
 pub(crate) const NO_OID_SET: gss_OID_set = ptr::null_mut();
 
 lazy_static! {
+    // copies of the oids built at runtime by `Oid::from_bytes`, each
+    // made once and never freed
+    static ref INTERNED: Mutex<HashSet<&'static [u8]>> = Mutex::new(HashSet::new());
     static ref OIDS: HashMap<Oid, &'static str> = HashMap::from_iter(
         [
             (GSS_NT_USER_NAME, "GSS_NT_USER_NAME"),
@@ -79,6 +93,7 @@ lazy_static! {
             (GSS_NT_ANONYMOUS, "GSS_NT_ANONYMOUS"),
             (GSS_NT_EXPORT_NAME, "GSS_NT_EXPORT_NAME"),
             (GSS_NT_COMPOSITE_EXPORT, "GSS_NT_COMPOSITE_EXPORT"),
+            #[cfg(any(feature = "mit", feature = "heimdal"))]
             (GSS_INQ_SSPI_SESSION_KEY, "GSS_INQ_SSPI_SESSION_KEY"),
             (GSS_INQ_NEGOEX_KEY, "GSS_INQ_NEGOEX_KEY"),
             (GSS_INQ_NEGOEX_VERIFY_KEY, "GSS_INQ_NEGOEX_VERIFY_KEY"),
@@ -86,6 +101,7 @@ lazy_static! {
             (GSS_SEC_CONTEXT_SASL_SSF, "GSS_SEC_CONTEXT_SASL_SSF"),
             (GSS_MECH_KRB5, "GSS_MECH_KRB5"),
             (GSS_MECH_IAKERB, "GSS_MECH_IAKERB"),
+            (GSS_MECH_SPNEGO, "GSS_MECH_SPNEGO"),
             (GSS_NT_KRB5_PRINCIPAL, "GSS_KRB5_NT_PRINCIPAL"),
             (GSS_KRB5_CRED_NO_CI_FLAGS_X, "GSS_KRB5_CRED_NO_CI_FLAGS_X"),
             (
@@ -107,6 +123,12 @@ lazy_static! {
  * some other implementation might be. So if that happens I guess file
  * a bug. */
 /// An Oid. Did I mention I hate OIDs.
+///
+/// An `Oid` never owns the encoding it points to, so it has no `Drop`
+/// and there is nothing to double free. The encoding is either one of
+/// our constants, one owned by the gssapi library, or one interned by
+/// `Oid::from_bytes` or `from_str`, all of which live as long as the
+/// process.
 #[repr(transparent)]
 #[derive(Clone, Copy)]
 pub struct Oid(gss_OID_desc);
@@ -119,15 +141,59 @@ unsafe impl Sync for Oid {}
 impl fmt::Debug for Oid {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match OIDS.get(self) {
-            None => write!(f, "{:?}", &*self as &[u8]),
+            None => fmt::Display::fmt(self, f),
             Some(name) => write!(f, "{}", name),
         }
     }
 }
 
+/// Oids are displayed in dotted decimal form, e.g.
+/// `1.2.840.113554.1.2.2`. If the gssapi library can't convert the oid
+/// the raw bytes are shown instead.
 impl fmt::Display for Oid {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        fmt::Debug::fmt(self, f)
+        match self.to_dotted() {
+            Ok(s) => write!(f, "{}", s),
+            Err(_) => write!(f, "{:?}", &*self as &[u8]),
+        }
+    }
+}
+
+/// Parse an oid in dotted decimal form, e.g.
+/// `1.2.840.113554.1.2.2`. If it is one of the oids this crate
+/// defines the matching constant is returned, otherwise the encoding
+/// is interned as by `Oid::from_bytes`, so parsing the same string
+/// again doesn't allocate. See `from_bytes` for the memory that
+/// costs.
+impl FromStr for Oid {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Oid, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut buf = BufRef::from(s.as_bytes());
+        let mut oid = ptr::null_mut::<gss_OID_desc>();
+        let major = unsafe {
+            gss_str_to_oid(
+                &mut minor as *mut OM_uint32,
+                buf.to_c(),
+                &mut oid as *mut gss_OID,
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            let parsed = unsafe { Oid::from_c(oid) };
+            let res = Oid::from_bytes(parsed);
+            unsafe {
+                gss_release_oid(&mut minor as *mut OM_uint32, &mut oid as *mut gss_OID);
+            }
+            Ok(res)
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: None,
+            })
+        }
     }
 }
 
@@ -176,6 +242,29 @@ impl From<gss_OID_desc> for Oid {
     }
 }
 
+/// The names of a mechanism attribute (RFC 5587), see
+/// `display_mech_attr`
+#[derive(Debug, Clone)]
+pub struct MechAttrInfo {
+    /// The name of the attribute, e.g. `GSS_C_MA_DELEG_CRED`
+    pub name: String,
+    /// A short description of the attribute
+    pub short_desc: String,
+    /// A longer description of the attribute
+    pub long_desc: String,
+}
+
+/// The SASL names of a mechanism (RFC 5801), see `Oid::sasl_name`
+#[derive(Debug, Clone)]
+pub struct SaslMechInfo {
+    /// The SASL mechanism name, e.g. `GS2-KRB5`
+    pub sasl_name: String,
+    /// The mechanism name, e.g. `krb5`
+    pub mech_name: String,
+    /// A human readable description of the mechanism
+    pub mech_description: String,
+}
+
 impl Oid {
     #[allow(dead_code)]
     pub(crate) unsafe fn from_c<'a>(ptr: gss_OID) -> &'a Oid {
@@ -197,6 +286,143 @@ impl Oid {
         let elements = ber.as_ptr() as *mut std::ffi::c_void;
         Oid(gss_OID_desc { length, elements })
     }
+
+    /// Convert the oid to dotted decimal form, e.g.
+    /// `1.2.840.113554.1.2.2` for krb5.
+    pub fn to_dotted(&self) -> Result<String, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut buf = Buf::empty();
+        let major = unsafe {
+            gss_oid_to_str(&mut minor as *mut OM_uint32, self.to_c(), buf.to_c())
+        };
+        if major == GSS_S_COMPLETE {
+            // gssapi formats oids like "{ 1 2 840 113554 1 2 2 }"
+            let s = String::from_utf8_lossy(&buf);
+            let components = s
+                .trim_matches(|c: char| c == '{' || c == '}' || c == '\0')
+                .split_whitespace()
+                .collect::<Vec<_>>();
+            Ok(components.join("."))
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: None,
+            })
+        }
+    }
+
+    /// Get the SASL names for this mechanism, for bridging it into
+    /// SASL as a GS2 mechanism (RFC 5801). Invalid UTF-8 sequences are
+    /// replaced.
+    pub fn sasl_name(&self) -> Result<SaslMechInfo, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut sasl_name = Buf::empty();
+        let mut mech_name = Buf::empty();
+        let mut mech_description = Buf::empty();
+        let major = unsafe {
+            gss_inquire_saslname_for_mech(
+                &mut minor as *mut OM_uint32,
+                self.to_c(),
+                sasl_name.to_c(),
+                mech_name.to_c(),
+                mech_description.to_c(),
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(SaslMechInfo {
+                sasl_name: String::from_utf8_lossy(&sasl_name).into_owned(),
+                mech_name: String::from_utf8_lossy(&mech_name).into_owned(),
+                mech_description: String::from_utf8_lossy(&mech_description).into_owned(),
+            })
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: self.known(),
+            })
+        }
+    }
+
+    /// Get the attributes of this mechanism (RFC 5587), e.g. whether
+    /// it supports delegation or channel bindings. Returns the
+    /// attributes the mechanism has, followed by all the attributes
+    /// it knows about. See `display_mech_attr` to describe them.
+    pub fn attributes(&self) -> Result<(OidSet, OidSet), Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut mech_attrs = ptr::null_mut::<gss_OID_set_desc>();
+        let mut known_attrs = ptr::null_mut::<gss_OID_set_desc>();
+        let major = unsafe {
+            gss_inquire_attrs_for_mech(
+                &mut minor as *mut OM_uint32,
+                self.to_c(),
+                &mut mech_attrs as *mut gss_OID_set,
+                &mut known_attrs as *mut gss_OID_set,
+            )
+        };
+        // wrap them right away so they are released on error too
+        let sets = (OidSet(mech_attrs), OidSet(known_attrs));
+        if major == GSS_S_COMPLETE {
+            Ok(sets)
+        } else {
+            Err(Error {
+                major: unsafe { MajorFlags::from_bits_unchecked(major) },
+                minor,
+                detail: None,
+                mech: self.known(),
+            })
+        }
+    }
+
+    /// The same as `from_slice`, but for encodings that aren't known
+    /// until runtime (e.g. read from a config file). `der` is the
+    /// contents of the DER encoding, without the tag and length
+    /// bytes, e.g. `b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x02"` for
+    /// krb5. Since OIDs must live in static memory, oids this crate
+    /// doesn't define are copied into a process wide table the first
+    /// time they are seen, and every later call with the same bytes
+    /// returns that copy.
+    ///
+    /// Those copies are leaked, they are never freed. The leak is
+    /// bounded by the total size of the distinct oids passed in over
+    /// the life of the process, which is small for oids from
+    /// configuration or code, but unbounded if they come from
+    /// somewhere an attacker controls, e.g. a peer's token. Don't
+    /// call this (or parse oids) with untrusted input in a long
+    /// running process.
+    pub fn from_bytes(der: &[u8]) -> Oid {
+        // only used for the lookup, it doesn't outlive `der`
+        let tmp = Oid(gss_OID_desc {
+            length: der.len() as OM_uint32,
+            elements: der.as_ptr() as *mut std::ffi::c_void,
+        });
+        if let Some(id) = tmp.known() {
+            return id;
+        }
+        let mut interned = INTERNED.lock().unwrap_or_else(|e| e.into_inner());
+        match interned.get(der) {
+            Some(ber) => Oid::from_slice(ber),
+            None => {
+                let ber: &'static [u8] = Box::leak(Vec::from(der).into_boxed_slice());
+                interned.insert(ber);
+                Oid::from_slice(ber)
+            }
+        }
+    }
+
+    /// Return our static copy of this oid if it is one we know, or
+    /// one interned by `from_bytes`, for storing in an `Error` without
+    /// borrowing memory that may be freed before the error is looked
+    /// at. Other oids give `None`.
+    pub(crate) fn known(&self) -> Option<Oid> {
+        if let Some((id, _)) = OIDS.get_key_value(self) {
+            return Some(*id);
+        }
+        let interned = INTERNED.lock().unwrap_or_else(|e| e.into_inner());
+        interned.get(&**self).map(|ber| Oid::from_slice(ber))
+    }
 }
 
 pub struct OidSetIter<'a> {
@@ -208,7 +434,7 @@ impl<'a> Iterator for OidSetIter<'a> {
     type Item = &'a Oid;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current < self.len() {
+        if self.current < self.set.len() {
             let res = Some(&self.set[self.current]);
             self.current += 1;
             res
@@ -274,6 +500,50 @@ impl<'a> IntoIterator for &'a OidSet {
     }
 }
 
+/// Collect oids into a new set.
+///
+/// # Panics
+///
+/// If gssapi fails to allocate the set or add a member to it, use
+/// `OidSet::new` and `OidSet::add` if you want to handle that.
+impl<'a> FromIterator<&'a Oid> for OidSet {
+    fn from_iter<T: IntoIterator<Item = &'a Oid>>(iter: T) -> Self {
+        let mut set = OidSet::new().expect("failed to create oid set");
+        for id in iter {
+            set.add(id).expect("failed to add oid to set");
+        }
+        set
+    }
+}
+
+/// The same as collecting `&Oid`s.
+///
+/// # Panics
+///
+/// If gssapi fails to allocate the set or add a member to it.
+impl FromIterator<Oid> for OidSet {
+    fn from_iter<T: IntoIterator<Item = Oid>>(iter: T) -> Self {
+        let mut set = OidSet::new().expect("failed to create oid set");
+        for id in iter {
+            set.add(&id).expect("failed to add oid to set");
+        }
+        set
+    }
+}
+
+/// gssapi has no function to duplicate a set, so this creates a new
+/// set and adds each member. Cloning an empty set gives an empty set.
+///
+/// # Panics
+///
+/// Like `collect`, if gssapi fails to allocate the new set or add a
+/// member to it.
+impl Clone for OidSet {
+    fn clone(&self) -> Self {
+        self.iter().collect()
+    }
+}
+
 impl fmt::Debug for OidSet {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         fmt::Debug::fmt(&self.into_iter().collect::<Vec<_>>(), f)
@@ -298,6 +568,8 @@ impl OidSet {
             Err(Error {
                 major: unsafe { MajorFlags::from_bits_unchecked(major) },
                 minor,
+                detail: None,
+                mech: None,
             })
         }
     }
@@ -311,6 +583,11 @@ impl OidSet {
         self.0
     }
 
+    /// Iterate over the oids in this set
+    pub fn iter(&self) -> OidSetIter<'_> {
+        self.into_iter()
+    }
+
     /// How many oids are in this set
     pub fn len(&self) -> usize {
         unsafe { (*self.0).count as usize }
@@ -332,6 +609,8 @@ impl OidSet {
             Err(Error {
                 major: unsafe { MajorFlags::from_bits_unchecked(major) },
                 minor,
+                detail: None,
+                mech: None,
             })
         }
     }
@@ -355,7 +634,178 @@ impl OidSet {
             Err(Error {
                 major: unsafe { MajorFlags::from_bits_unchecked(major) },
                 minor,
+                detail: None,
+                mech: None,
             })
         }
     }
 }
+
+/// Return the set of mechanisms supported by the gssapi
+/// implementation.
+pub fn indicate_mechs() -> Result<OidSet, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut out = ptr::null_mut::<gss_OID_set_desc>();
+    let major = unsafe {
+        gss_indicate_mechs(&mut minor as *mut OM_uint32, &mut out as *mut gss_OID_set)
+    };
+    if major == GSS_S_COMPLETE {
+        Ok(OidSet(out))
+    } else {
+        Err(Error {
+            major: unsafe { MajorFlags::from_bits_unchecked(major) },
+            minor,
+            detail: None,
+            mech: None,
+        })
+    }
+}
+
+/// Return the set of name types supported by `mech`, e.g. for krb5
+/// this includes `GSS_NT_HOSTBASED_SERVICE` and
+/// `GSS_NT_KRB5_PRINCIPAL`.
+pub fn inquire_names_for_mech(mech: &Oid) -> Result<OidSet, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut out = ptr::null_mut::<gss_OID_set_desc>();
+    let major = unsafe {
+        gss_inquire_names_for_mech(
+            &mut minor as *mut OM_uint32,
+            mech.to_c(),
+            &mut out as *mut gss_OID_set,
+        )
+    };
+    if major == GSS_S_COMPLETE {
+        Ok(OidSet(out))
+    } else {
+        Err(Error {
+            major: unsafe { MajorFlags::from_bits_unchecked(major) },
+            minor,
+            detail: None,
+            mech: mech.known(),
+        })
+    }
+}
+
+/// Return the mechanism whose SASL name (RFC 5801) is `name`, e.g.
+/// `GS2-KRB5` for krb5. This is the inverse of `Oid::sasl_name`.
+pub fn mech_for_saslname(name: &str) -> Result<Oid, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut name = BufRef::from(name.as_bytes());
+    let mut mech = ptr::null_mut::<gss_OID_desc>();
+    let major = unsafe {
+        gss_inquire_mech_for_saslname(
+            &mut minor as *mut OM_uint32,
+            name.to_c(),
+            &mut mech as *mut gss_OID,
+        )
+    };
+    if major == GSS_S_COMPLETE {
+        // the library returns its own mechanism oid, which lives as
+        // long as the process
+        Ok(unsafe { *Oid::from_c(mech) })
+    } else {
+        Err(Error {
+            major: unsafe { MajorFlags::from_bits_unchecked(major) },
+            minor,
+            detail: None,
+            mech: None,
+        })
+    }
+}
+
+/// Describe the mechanism attribute `attr` (RFC 5587), e.g. one
+/// returned by `Oid::attributes`. Invalid UTF-8 sequences are
+/// replaced.
+pub fn display_mech_attr(attr: &Oid) -> Result<MechAttrInfo, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut name = Buf::empty();
+    let mut short_desc = Buf::empty();
+    let mut long_desc = Buf::empty();
+    let major = unsafe {
+        gss_display_mech_attr(
+            &mut minor as *mut OM_uint32,
+            attr.to_c(),
+            name.to_c(),
+            short_desc.to_c(),
+            long_desc.to_c(),
+        )
+    };
+    if major == GSS_S_COMPLETE {
+        Ok(MechAttrInfo {
+            name: String::from_utf8_lossy(&name).into_owned(),
+            short_desc: String::from_utf8_lossy(&short_desc).into_owned(),
+            long_desc: String::from_utf8_lossy(&long_desc).into_owned(),
+        })
+    } else {
+        Err(Error {
+            major: unsafe { MajorFlags::from_bits_unchecked(major) },
+            minor,
+            detail: None,
+            mech: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_bytes_interns() {
+        let krb5 = Oid::from_bytes(&GSS_MECH_KRB5);
+        assert_eq!(krb5.0.elements, GSS_MECH_KRB5.0.elements);
+        let der = vec![0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x02, 0x0a];
+        let a = Oid::from_bytes(&der);
+        let b = Oid::from_bytes(&der.clone());
+        assert_eq!(&*a, &der[..]);
+        assert_eq!(a.0.elements, b.0.elements);
+    }
+
+    #[test]
+    fn known_includes_interned() {
+        assert_eq!(GSS_MECH_KRB5.known(), Some(GSS_MECH_KRB5));
+        let mut der = vec![0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x02, 0x0b];
+        let lent = Oid::from(gss_OID_desc {
+            length: der.len() as OM_uint32,
+            elements: der.as_mut_ptr() as *mut std::ffi::c_void,
+        });
+        assert_eq!(lent.known(), None);
+        let interned = Oid::from_bytes(&der);
+        let known = lent.known().unwrap();
+        assert_eq!(known, interned);
+        assert_eq!(known.0.elements, interned.0.elements);
+    }
+
+    #[test]
+    fn from_str_interns() {
+        let a = "1.3.6.1.4.1.311.2.2.30".parse::<Oid>().unwrap();
+        let b = "1.3.6.1.4.1.311.2.2.30".parse::<Oid>().unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a.0.elements, b.0.elements);
+    }
+
+    #[test]
+    fn dotted_round_trip() {
+        let krb5 = "1.2.840.113554.1.2.2".parse::<Oid>().unwrap();
+        assert_eq!(krb5, GSS_MECH_KRB5);
+        assert_eq!(krb5.0.elements, GSS_MECH_KRB5.0.elements);
+        assert_eq!(GSS_MECH_KRB5.to_dotted().unwrap(), "1.2.840.113554.1.2.2");
+        assert_eq!(krb5.to_string(), "1.2.840.113554.1.2.2");
+    }
+
+    #[test]
+    fn oid_set_clone() {
+        let empty = OidSet::new().unwrap();
+        assert_eq!(empty.clone().len(), 0);
+        let mut set = OidSet::new().unwrap();
+        set.add(&GSS_MECH_KRB5).unwrap();
+        set.add(&GSS_MECH_SPNEGO).unwrap();
+        let copy = set.clone();
+        assert_eq!(copy.len(), 2);
+        assert!(copy.contains(&GSS_MECH_KRB5).unwrap());
+        assert!(copy.contains(&GSS_MECH_SPNEGO).unwrap());
+        drop(set);
+        let members = copy.iter().copied().collect::<Vec<_>>();
+        assert_eq!(members, [GSS_MECH_KRB5, GSS_MECH_SPNEGO]);
+    }
+}
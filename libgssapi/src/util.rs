@@ -1,14 +1,39 @@
 use bytes;
 use libgssapi_sys::{
-    gss_buffer_desc, gss_buffer_desc_struct, gss_buffer_t, gss_release_buffer, size_t,
-    OM_uint32, GSS_S_COMPLETE,
+    gss_buffer_desc, gss_buffer_desc_struct, gss_buffer_set_t, gss_buffer_t,
+    gss_release_buffer, gss_release_buffer_set, size_t, OM_uint32, _GSS_C_INDEFINITE,
+    GSS_S_COMPLETE,
 };
 use std::{
-    ffi,
+    ffi, fmt,
     marker::PhantomData,
-    ops::{Deref, DerefMut, Drop},
+    ops::{Deref, DerefMut, Drop, Index},
     ptr, slice,
+    time::Duration,
 };
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+/// Convert an optional time request into seconds for gssapi. `None`
+/// means as long as possible, and so does anything too long to fit
+/// in an `OM_uint32`, rather than silently wrapping around to some
+/// short time.
+pub(crate) fn time_to_c(time: Option<Duration>) -> OM_uint32 {
+    match time {
+        None => _GSS_C_INDEFINITE,
+        Some(d) => d.as_secs().min(_GSS_C_INDEFINITE as u64) as OM_uint32,
+    }
+}
+
+/// Convert a lifetime in seconds from gssapi, `None` means the
+/// lifetime is indefinite.
+pub(crate) fn time_from_c(time: OM_uint32) -> Option<Duration> {
+    if time == _GSS_C_INDEFINITE {
+        None
+    } else {
+        Some(Duration::from_secs(time as u64))
+    }
+}
 
 #[cfg(feature = "iov")]
 mod iov {
@@ -185,6 +210,160 @@ mod iov {
 #[cfg(feature = "iov")]
 pub use iov::*;
 
+/// Overwrite `buf` with zeros in a way the compiler won't optimize
+/// away, before memory holding secrets is freed. This uses `zeroize`
+/// when that feature is enabled, and volatile writes otherwise.
+pub(crate) fn zero(buf: &mut [u8]) {
+    #[cfg(feature = "zeroize")]
+    buf.zeroize();
+    #[cfg(not(feature = "zeroize"))]
+    {
+        for b in buf.iter_mut() {
+            unsafe { ptr::write_volatile(b, 0) };
+        }
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+/// A set of buffers we got from gssapi, e.g. from
+/// `SecurityContext::inquire_by_oid` or `Name::attributes`. The
+/// buffers are read in place, and the set is deallocated via the
+/// library routine when it is dropped. With the `zeroize` feature the
+/// contents are zeroed first, since inquiries can return key
+/// material, e.g. the session key.
+pub struct BufferSet(gss_buffer_set_t);
+
+unsafe impl Send for BufferSet {}
+unsafe impl Sync for BufferSet {}
+
+impl Drop for BufferSet {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            #[cfg(feature = "zeroize")]
+            self.zero();
+            let mut minor = GSS_S_COMPLETE;
+            let _major = unsafe {
+                gss_release_buffer_set(
+                    &mut minor as *mut OM_uint32,
+                    &mut self.0 as *mut gss_buffer_set_t,
+                )
+            };
+        }
+    }
+}
+
+impl fmt::Debug for BufferSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl Index<usize> for BufferSet {
+    type Output = [u8];
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match self.get(index) {
+            Some(b) => b,
+            None => panic!("index {} out of bounds {}", index, self.len()),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a BufferSet {
+    type Item = &'a [u8];
+    type IntoIter = BufferSetIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct BufferSetIter<'a> {
+    current: usize,
+    set: &'a BufferSet,
+}
+
+impl<'a> Iterator for BufferSetIter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let b = self.set.get(self.current)?;
+        self.current += 1;
+        Some(b)
+    }
+}
+
+impl<'a> ExactSizeIterator for BufferSetIter<'a> {
+    fn len(&self) -> usize {
+        self.set.len() - self.current
+    }
+}
+
+impl BufferSet {
+    pub(crate) unsafe fn from_c(set: gss_buffer_set_t) -> BufferSet {
+        BufferSet(set)
+    }
+
+    // zero every buffer in the set, see `zero`
+    pub(crate) fn zero(&mut self) {
+        if !self.0.is_null() {
+            unsafe {
+                for i in 0..(*self.0).count {
+                    let b = &*(*self.0).elements.add(i as usize);
+                    if !b.value.is_null() {
+                        let len = b.length as usize;
+                        zero(slice::from_raw_parts_mut(b.value as *mut u8, len));
+                    }
+                }
+            }
+        }
+    }
+
+    /// The number of buffers in the set
+    pub fn len(&self) -> usize {
+        if self.0.is_null() {
+            0
+        } else {
+            unsafe { (*self.0).count as usize }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the buffer at `index`, or `None` if it is out of bounds
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        if index >= self.len() {
+            None
+        } else {
+            unsafe {
+                let b = &*(*self.0).elements.add(index);
+                if b.value.is_null() {
+                    Some(&[])
+                } else {
+                    Some(slice::from_raw_parts(
+                        b.value as *const u8,
+                        b.length as usize,
+                    ))
+                }
+            }
+        }
+    }
+
+    pub fn iter(&self) -> BufferSetIter<'_> {
+        BufferSetIter {
+            current: 0,
+            set: self,
+        }
+    }
+
+    /// Copy the buffers out of the set
+    pub fn to_vec(&self) -> Vec<Vec<u8>> {
+        self.iter().map(Vec::from).collect()
+    }
+}
+
 /* This type is dangerous, because we can't force C not to modify the
  * contents of the pointer, and that could have serious
  * consquences. We must use this type ONLY with gssapi functions that
@@ -221,7 +400,9 @@ impl<'a> BufRef<'a> {
 }
 
 /// This represents an owned buffer we got from gssapi, it will be
-/// deallocated via the library routine when it is dropped.
+/// deallocated via the library routine when it is dropped. With the
+/// `zeroize` feature the contents are zeroed first, since buffers
+/// can hold plaintext or key material (e.g. from `pseudo_random`).
 #[repr(transparent)]
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -244,9 +425,23 @@ impl DerefMut for Buf {
     }
 }
 
+impl AsRef<[u8]> for Buf {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl AsMut<[u8]> for Buf {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
 impl Drop for Buf {
     fn drop(&mut self) {
         if !self.0.value.is_null() {
+            #[cfg(feature = "zeroize")]
+            zero(self);
             let mut minor = GSS_S_COMPLETE;
             let _major = unsafe {
                 gss_release_buffer(
@@ -310,3 +505,24 @@ impl GssBytes {
         self.buf
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_to_c_saturates() {
+        assert_eq!(time_to_c(None), _GSS_C_INDEFINITE);
+        assert_eq!(
+            time_to_c(Some(Duration::from_secs(u64::MAX))),
+            _GSS_C_INDEFINITE
+        );
+        assert_eq!(
+            time_to_c(Some(Duration::from_secs(u32::MAX as u64))),
+            u32::MAX
+        );
+        let just_under = Duration::from_secs(u32::MAX as u64 - 1);
+        assert_eq!(time_to_c(Some(just_under)), u32::MAX - 1);
+        assert_eq!(time_to_c(Some(Duration::from_secs(0))), 0);
+    }
+}
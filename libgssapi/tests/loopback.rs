@@ -0,0 +1,197 @@
+//! Establish a krb5 context between a client and a server in the same
+//! process, directly and through SPNEGO, then check the message
+//! protection round trips. This needs a working kerberos environment,
+//! a TGT for the client and a keytab for the service (e.g. via
+//! `KRB5_KTNAME`), so it only runs when `GSS_TEST_PRINCIPAL` is set to
+//! the host based service name to use, e.g.
+//!
+//! `GSS_TEST_PRINCIPAL=nfs@server.example.com cargo test --test loopback`
+//!
+//! The separate stores test also acquires an initiator credential for
+//! the service from its keytab, `KRB5_KTNAME` or `/etc/krb5.keytab`.
+//!
+//! The anonymous test also needs anonymous PKINIT to be set up on the
+//! KDC, so it only runs if `GSS_TEST_ANONYMOUS` is set as well.
+use libgssapi::{
+    context::{ClientCtx, CtxFlags, PrfKey, Qop, SecurityContext, ServerCtx},
+    credential::{Cred, CredStore, CredUsage},
+    error::Error,
+    name::Name,
+    oid::{Oid, OidSet, GSS_MECH_KRB5, GSS_MECH_SPNEGO, GSS_NT_HOSTBASED_SERVICE},
+    util::Buf,
+};
+use std::{
+    env,
+    sync::{Arc, Barrier},
+    thread,
+};
+
+fn establish(
+    service: &str,
+    mech: &'static Oid,
+    client: Option<&Name>,
+    flags: CtxFlags,
+) -> Result<(ClientCtx, ServerCtx), Error> {
+    let mut mechs = OidSet::new()?;
+    mechs.add(mech)?;
+    let name = Name::new(service.as_bytes(), Some(&GSS_NT_HOSTBASED_SERVICE))?;
+    let cname = name.canonicalize(Some(&GSS_MECH_KRB5))?;
+    let server_cred = Cred::acquire(Some(&cname), None, CredUsage::Accept, Some(&mechs))?;
+    let client_cred = Cred::acquire(client, None, CredUsage::Initiate, Some(&mechs))?;
+    let mut server_ctx = ServerCtx::new(Some(server_cred), None);
+    let mut client_ctx = ClientCtx::new(client_cred, cname, flags, Some(mech), None);
+    let mut server_tok: Option<Buf> = None;
+    loop {
+        match client_ctx.step(server_tok.as_deref())? {
+            None => break,
+            Some(client_tok) => match server_ctx.step(&client_tok)? {
+                None => break,
+                Some(tok) => server_tok = Some(tok),
+            },
+        }
+    }
+    assert!(client_ctx.is_complete());
+    assert!(server_ctx.is_complete());
+    Ok((client_ctx, server_ctx))
+}
+
+#[test]
+fn loopback() -> Result<(), Error> {
+    let service = match env::var("GSS_TEST_PRINCIPAL") {
+        Ok(service) => service,
+        Err(_) => return Ok(()),
+    };
+    let (mut client_ctx, mut server_ctx) =
+        establish(&service, &GSS_MECH_KRB5, None, CtxFlags::GSS_C_MUTUAL_FLAG)?;
+    let exported_client = server_ctx.source_name_exported()?;
+    assert_eq!(&*exported_client, &*client_ctx.source_name()?.export()?);
+    let msg = client_ctx.wrap(true, b"super secret message")?;
+    assert_eq!(&*server_ctx.unwrap(&msg)?, b"super secret message");
+    let reply = server_ctx.wrap(true, b"super secret reply")?;
+    let (decoded, info) = client_ctx.unwrap_info(&reply)?;
+    assert_eq!(&*decoded, b"super secret reply");
+    assert!(info.encrypted);
+    let batch = client_ctx.wrap_many(true, &[b"first", b"second"])?;
+    assert_eq!(&*server_ctx.unwrap(&batch[0])?, b"first");
+    assert_eq!(&*server_ctx.unwrap(&batch[1])?, b"second");
+    let mic = client_ctx.get_mic(b"signed message")?;
+    server_ctx.verify_mic(b"signed message", &mic)?;
+    assert!(server_ctx.verify_mic(b"tampered message", &mic).is_err());
+    let client_key = client_ctx.pseudo_random(PrfKey::Full, b"loopback", 32)?;
+    let server_key = server_ctx.pseudo_random(PrfKey::Full, b"loopback", 32)?;
+    assert_eq!(&*client_key, &*server_key);
+    #[cfg(any(feature = "mit", feature = "heimdal"))]
+    {
+        let client_session = client_ctx.session_key()?;
+        let server_session = server_ctx.session_key()?;
+        assert!(!client_session.key().is_empty());
+        assert_eq!(client_session.key(), server_session.key());
+        assert_eq!(client_session.enctype_oid, server_session.enctype_oid);
+    }
+    let limit = client_ctx.wrap_size_limit(true, Qop::DEFAULT, 1024)?;
+    let record = vec![0x5a; limit as usize];
+    let wrapped = client_ctx.wrap(true, &record)?;
+    assert!(wrapped.len() <= 1024);
+    assert_eq!(&*server_ctx.unwrap(&wrapped)?, &record[..]);
+    Ok(())
+}
+
+#[test]
+fn spnego() -> Result<(), Error> {
+    let service = match env::var("GSS_TEST_PRINCIPAL") {
+        Ok(service) => service,
+        Err(_) => return Ok(()),
+    };
+    let (mut client_ctx, mut server_ctx) = establish(
+        &service,
+        &GSS_MECH_SPNEGO,
+        None,
+        CtxFlags::GSS_C_MUTUAL_FLAG,
+    )?;
+    // the negotiated mech, not spnego itself
+    assert_eq!(client_ctx.mechanism()?, &GSS_MECH_KRB5);
+    assert_eq!(server_ctx.mechanism()?, &GSS_MECH_KRB5);
+    let msg = client_ctx.wrap(true, b"negotiated message")?;
+    assert_eq!(&*server_ctx.unwrap(&msg)?, b"negotiated message");
+    Ok(())
+}
+
+#[test]
+fn anonymous() -> Result<(), Error> {
+    let service = match env::var("GSS_TEST_PRINCIPAL") {
+        Ok(service) if env::var_os("GSS_TEST_ANONYMOUS").is_some() => service,
+        _ => return Ok(()),
+    };
+    let anon = Name::anonymous()?;
+    let flags = CtxFlags::GSS_C_MUTUAL_FLAG | CtxFlags::GSS_C_ANON_FLAG;
+    let (mut client_ctx, mut server_ctx) =
+        establish(&service, &GSS_MECH_KRB5, Some(&anon), flags)?;
+    assert!(client_ctx.flags()?.contains(CtxFlags::GSS_C_ANON_FLAG));
+    assert!(server_ctx.flags()?.contains(CtxFlags::GSS_C_ANON_FLAG));
+    assert!(server_ctx.source_name()?.is_anonymous()?);
+    let msg = client_ctx.wrap(true, b"anonymous message")?;
+    assert_eq!(&*server_ctx.unwrap(&msg)?, b"anonymous message");
+    Ok(())
+}
+
+#[test]
+fn separate_stores() -> Result<(), Error> {
+    let service = match env::var("GSS_TEST_PRINCIPAL") {
+        Ok(service) => service,
+        Err(_) => return Ok(()),
+    };
+    let name = Name::new(service.as_bytes(), Some(&GSS_NT_HOSTBASED_SERVICE))?;
+    let cname = name.canonicalize(Some(&GSS_MECH_KRB5))?;
+    let keytab = env::var("KRB5_KTNAME").unwrap_or_else(|_| "/etc/krb5.keytab".into());
+    let user = Cred::acquire(None, None, CredUsage::Initiate, None)?;
+    let user_name = user.name()?;
+    let service_name = cname.clone();
+    let ready = Arc::new(Barrier::new(2));
+    // the client's ticket, copied from the default ccache into its own
+    let user_thread = {
+        let ready = ready.clone();
+        thread::spawn(move || -> Result<Name, Error> {
+            let store = CredStore::new().set("ccache", "MEMORY:loopback-user");
+            user.store_into(CredUsage::Initiate, None, true, true, &store)?;
+            ready.wait();
+            let cred = Cred::acquire_from(None, None, CredUsage::Initiate, None, &store)?;
+            cred.name()
+        })
+    };
+    // the service's own ticket, from its keytab
+    let service_thread = thread::spawn(move || -> Result<Name, Error> {
+        let store = CredStore::new()
+            .set("ccache", "MEMORY:loopback-service")
+            .set("client_keytab", &keytab);
+        ready.wait();
+        let usage = CredUsage::Initiate;
+        let cred = Cred::acquire_from(Some(&service_name), None, usage, None, &store)?;
+        cred.name()
+    });
+    let from_user_store = user_thread.join().unwrap()?;
+    let from_service_store = service_thread.join().unwrap()?;
+    assert_eq!(from_user_store, user_name);
+    assert_eq!(from_service_store, cname);
+    assert_ne!(from_user_store, from_service_store);
+    Ok(())
+}
+
+#[test]
+fn both_usage() -> Result<(), Error> {
+    let service = match env::var("GSS_TEST_PRINCIPAL") {
+        Ok(service) => service,
+        Err(_) => return Ok(()),
+    };
+    let name = Name::new(service.as_bytes(), Some(&GSS_NT_HOSTBASED_SERVICE))?;
+    let cname = name.canonicalize(Some(&GSS_MECH_KRB5))?;
+    let accept = Cred::acquire(Some(&cname), None, CredUsage::Accept, None)?;
+    assert_eq!(accept.info()?.usage, CredUsage::Accept);
+    assert_eq!(accept.usage()?, CredUsage::Accept);
+    // the service may or may not be able to get its own ticket, but
+    // if asking for both succeeds the credential must work both ways
+    if let Ok(both) = Cred::acquire(Some(&cname), None, CredUsage::Both, None) {
+        assert_eq!(both.split_usage()?, Some(CredUsage::Both));
+        assert_eq!(both.info()?.usage, CredUsage::Both);
+    }
+    Ok(())
+}